@@ -0,0 +1,171 @@
+use log::error;
+use serenity::{
+    builder::{CreateEmbed, CreateMessage},
+    model::{
+        id::{ChannelId, GuildId, UserId},
+        Color, Timestamp,
+    },
+    prelude::Context,
+};
+
+use crate::{role_menu::RoleMenu, sync::SyncSummary};
+
+/// Posts a record of role changes to the channel a guild's moderators chose, if any
+#[derive(Clone, Copy)]
+pub struct AuditLog {
+    channel_id: Option<ChannelId>,
+}
+
+impl AuditLog {
+    pub fn new(channel_id: Option<ChannelId>) -> Self {
+        Self { channel_id }
+    }
+
+    pub async fn role_granted(
+        &self,
+        ctx: &Context,
+        menu: &RoleMenu,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: u64,
+    ) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Role granted")
+                .color(Color::DARK_GREEN)
+                .description(format!(
+                    "<@{user_id}> picked <@&{role_id}> from {}",
+                    describe_menu(menu, guild_id)
+                )),
+        )
+        .await;
+    }
+
+    pub async fn role_removed(
+        &self,
+        ctx: &Context,
+        menu: &RoleMenu,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: u64,
+    ) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Role removed")
+                .color(Color::DARK_RED)
+                .description(format!(
+                    "<@{user_id}> gave up <@&{role_id}> from {}",
+                    describe_menu(menu, guild_id)
+                )),
+        )
+        .await;
+    }
+
+    pub async fn role_expired(&self, ctx: &Context, user_id: UserId, role_id: u64) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Role expired")
+                .color(Color::DARK_RED)
+                .description(format!("<@{user_id}>'s <@&{role_id}> ran out")),
+        )
+        .await;
+    }
+
+    pub async fn role_enabled(
+        &self,
+        ctx: &Context,
+        admin_id: UserId,
+        menu_name: &str,
+        role_id: u64,
+    ) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Role enabled")
+                .color(Color::BLURPLE)
+                .description(format!(
+                    "<@{admin_id}> enabled <@&{role_id}> in the {menu_name} menu"
+                )),
+        )
+        .await;
+    }
+
+    pub async fn role_disabled(
+        &self,
+        ctx: &Context,
+        admin_id: UserId,
+        menu_name: &str,
+        role_id: u64,
+    ) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Role disabled")
+                .color(Color::BLURPLE)
+                .description(format!(
+                    "<@{admin_id}> disabled <@&{role_id}> in the {menu_name} menu"
+                )),
+        )
+        .await;
+    }
+
+    pub async fn message_posted(
+        &self,
+        ctx: &Context,
+        admin_id: UserId,
+        menu_name: &str,
+        menu: &RoleMenu,
+        guild_id: GuildId,
+    ) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Menu posted")
+                .color(Color::BLURPLE)
+                .description(format!(
+                    "<@{admin_id}> posted the {menu_name} menu at {}",
+                    describe_menu(menu, guild_id)
+                )),
+        )
+        .await;
+    }
+
+    pub async fn menu_synced(&self, ctx: &Context, menu_name: &str, summary: &SyncSummary) {
+        self.post(
+            ctx,
+            CreateEmbed::new()
+                .title("Menu synced")
+                .color(Color::BLURPLE)
+                .description(format!("Synced the {menu_name} menu: {summary}")),
+        )
+        .await;
+    }
+
+    async fn post(&self, ctx: &Context, embed: CreateEmbed) {
+        if let Some(channel_id) = self.channel_id {
+            if let Err(e) = channel_id
+                .send_message(
+                    ctx,
+                    CreateMessage::new().embed(embed.timestamp(Timestamp::now())),
+                )
+                .await
+            {
+                error!(
+                    "Could not post to audit log channel {:?}: {:?}",
+                    channel_id, e
+                );
+            }
+        }
+    }
+}
+
+/// Links to the menu's message where possible, since self-service events do not know its name
+fn describe_menu(menu: &RoleMenu, guild_id: GuildId) -> String {
+    match (menu.get_channel_id(), menu.get_message_id()) {
+        (Some(channel_id), Some(message_id)) => message_id.link(channel_id, Some(guild_id)),
+        _ => "a menu".to_owned(),
+    }
+}
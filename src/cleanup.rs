@@ -0,0 +1,163 @@
+use log::error;
+use serenity::{
+    model::id::{ChannelId, EmojiId, GuildId, MessageId, RoleId},
+    prelude::Context,
+};
+
+use crate::{database::Database, emoji::EmojiIndex};
+
+/// Removes a deleted role from every menu, along with any requirements naming it and any pending
+/// expirations
+pub async fn prune_deleted_role(ctx: &Context, db: &Database, guild_id: GuildId, role_id: RoleId) {
+    if db.get_guild_data(guild_id).is_none() {
+        return;
+    }
+
+    let role_id = role_id.get();
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    let mut warnings = Vec::new();
+    for (name, menu) in data.get_menus_mut() {
+        if menu.has_role(role_id) {
+            menu.remove_role(ctx, guild_id, role_id).await;
+            warnings.push(format!(
+                "A role in the {name} menu was deleted, so it was removed from the menu"
+            ));
+        }
+        if menu.forget_bundled_role(ctx, guild_id, role_id).await {
+            warnings.push(format!(
+                "A role bundled with others in the {name} menu was deleted, so members no longer \
+                 get it from the menu"
+            ));
+        }
+        for menu_role in menu.forget_requirement_role(role_id) {
+            warnings.push(format!(
+                "<@&{menu_role}> in the {name} menu required a deleted role, so it no longer has \
+                 that requirement"
+            ));
+        }
+    }
+    for warning in warnings {
+        data.add_warning(warning);
+    }
+    data.forget_expiring_role(role_id);
+    data.commit();
+}
+
+/// Removes roles whose custom emoji was deleted from every menu in the guild.
+///
+/// Menus may use emoji from any guild the bot is in, so an emoji only counts as deleted once it
+/// is missing from the whole index, which must already reflect the change.
+pub async fn prune_deleted_emoji(
+    ctx: &Context,
+    db: &Database,
+    emoji_index: &EmojiIndex,
+    guild_id: GuildId,
+) {
+    let exists = |emoji_id: EmojiId| emoji_index.contains(emoji_id);
+    let stale: Vec<(String, Vec<u64>)> = db
+        .get_guild_data(guild_id)
+        .map(|data| {
+            data.get_menus()
+                .map(|(name, menu)| (name.clone(), menu.find_roles_with_missing_emoji(exists)))
+                .filter(|(_, roles)| !roles.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    if stale.is_empty() {
+        return;
+    }
+
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    for (name, roles) in stale {
+        if let Some(menu) = data.get_menu_mut(&name) {
+            for role_id in &roles {
+                menu.remove_role(ctx, guild_id, *role_id).await;
+            }
+        }
+        for role_id in roles {
+            data.add_warning(format!(
+                "The emoji for <@&{role_id}> in the {name} menu was deleted, so the role was \
+                 removed from the menu. Enable it again with a new emoji to restore it."
+            ));
+        }
+    }
+    data.commit();
+}
+
+/// Forgets menu messages that were deleted, so they can be posted again. A menu spread over
+/// several messages is of little use once one is gone, so the rest are deleted along with it.
+pub async fn forget_deleted_messages(
+    ctx: &Context,
+    db: &Database,
+    guild_id: GuildId,
+    message_ids: &[MessageId],
+) {
+    let affected = db.get_guild_data(guild_id).is_some_and(|data| {
+        message_ids
+            .iter()
+            .any(|message_id| data.find_menu_by_message(*message_id).is_some())
+    });
+    if !affected {
+        return;
+    }
+
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    let mut warnings = Vec::new();
+    for (name, menu) in data.get_menus_mut() {
+        if message_ids
+            .iter()
+            .any(|message_id| menu.has_message(*message_id))
+        {
+            if let Err(e) = menu.delete_message(ctx).await {
+                error!(
+                    "Could not delete the rest of the {} menu in guild {:?}: {:?}",
+                    name, guild_id, e
+                );
+                menu.clear_message();
+            }
+            warnings.push(format!(
+                "The message for the {name} menu was deleted. Post it again with \
+                 /role self-service message."
+            ));
+        }
+    }
+    for warning in warnings {
+        data.add_warning(warning);
+    }
+    data.commit();
+}
+
+/// Forgets menu messages whose channel was deleted, so they can be posted elsewhere
+pub async fn forget_deleted_channel(db: &Database, guild_id: GuildId, channel_id: ChannelId) {
+    let affected = db.get_guild_data(guild_id).is_some_and(|data| {
+        data.get_menus()
+            .any(|(_, menu)| menu.get_channel_id() == Some(channel_id))
+    });
+    if !affected {
+        return;
+    }
+
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    let mut warnings = Vec::new();
+    for (name, menu) in data.get_menus_mut() {
+        if menu.get_channel_id() == Some(channel_id) {
+            menu.clear_message();
+            warnings.push(format!(
+                "The channel for the {name} menu was deleted. Post it in another channel with \
+                 /role self-service message."
+            ));
+        }
+    }
+    for warning in warnings {
+        data.add_warning(warning);
+    }
+    data.commit();
+}
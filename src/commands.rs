@@ -1,11 +1,28 @@
 use serenity::{
     builder::{CreateCommand, CreateCommandOption},
-    model::{application::CommandOptionType, Permissions},
+    model::{application::CommandOptionType, channel::ChannelType, Permissions},
 };
 
 #[cfg(debug_assertions)]
 use serenity::{client::Context, model::application::Command};
 
+/// Keeps menu names short enough to fit comfortably in messages and component IDs
+const MENU_NAME_MAX_LENGTH: u16 = 32;
+
+/// Discord allows at most 100 characters in a select menu option's description
+const ROLE_DESCRIPTION_MAX_LENGTH: u16 = 100;
+
+/// Discord limits the length of each part of an embed
+const EMBED_TITLE_MAX_LENGTH: u16 = 256;
+const EMBED_DESCRIPTION_MAX_LENGTH: u16 = 4096;
+const EMBED_FOOTER_MAX_LENGTH: u16 = 2048;
+
+/// Temporary roles last at most a year
+const DURATION_MAX_HOURS: u64 = 24 * 365;
+
+/// Discord allows at most 25 options in a select menu
+const SELECT_MENU_MAX_OPTIONS: u64 = 25;
+
 pub fn create() -> CreateCommand {
     CreateCommand::new("role")
         .dm_permission(false)
@@ -23,6 +40,15 @@ pub fn create() -> CreateCommand {
                     "enable",
                     "enable a role for self-service enrollment",
                 )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to add the role to",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
                 .add_sub_option(
                     CreateCommandOption::new(CommandOptionType::Role, "role", "the role to enable")
                         .required(true),
@@ -33,6 +59,7 @@ pub fn create() -> CreateCommand {
                         "emoji",
                         "the emoji to associate with this role",
                     )
+                    .set_autocomplete(true)
                     .required(true),
                 ),
             )
@@ -42,6 +69,15 @@ pub fn create() -> CreateCommand {
                     "disable",
                     "disable a role for self-service enrollment",
                 )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to remove the role from",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
                 .add_sub_option(
                     CreateCommandOption::new(
                         CommandOptionType::Role,
@@ -57,13 +93,531 @@ pub fn create() -> CreateCommand {
                     "message",
                     "create a message for users to react to",
                 )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to post",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
                 .add_sub_option(
                     CreateCommandOption::new(
                         CommandOptionType::Channel,
                         "channel",
                         "the channel to message in",
                     )
+                    .channel_types(vec![ChannelType::Text])
+                    .required(true),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "move",
+                    "delete a menu's message and post it again in another channel",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to move",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
                     .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Channel,
+                        "channel",
+                        "the channel to move the menu to",
+                    )
+                    .channel_types(vec![ChannelType::Text])
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "migrate-roles",
+                    "whether members who reacted keep their roles instead of picking again, \
+                     defaults to true",
+                )),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "repost",
+                    "delete a menu's message and post it again in the same channel",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to repost",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "migrate-roles",
+                    "whether members who reacted keep their roles instead of picking again, \
+                     defaults to true",
+                )),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "style",
+                    "choose how members pick roles from a menu",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to change",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "style",
+                        "how members pick roles",
+                    )
+                    .add_string_choice("reactions", "reactions")
+                    .add_string_choice("buttons", "buttons")
+                    .add_string_choice("select", "select")
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "min-choices",
+                        "the fewest roles a member must pick from a select menu",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(SELECT_MENU_MAX_OPTIONS),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "max-choices",
+                        "the most roles a member may pick from a select menu",
+                    )
+                    .min_int_value(1)
+                    .max_int_value(SELECT_MENU_MAX_OPTIONS),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "describe",
+                    "explain what a role is for in its menu's message",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu the role is in",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "role",
+                        "the role to describe",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "description",
+                        "what the role is for, or empty to remove the description",
+                    )
+                    .max_length(ROLE_DESCRIPTION_MAX_LENGTH),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "reorder",
+                    "move a role within a menu, or sort all of its roles",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to reorder",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Role,
+                    "role",
+                    "the role to move",
+                ))
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "position",
+                        "where to move the role, starting from 1 at the top",
+                    )
+                    .min_int_value(1),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "sort",
+                        "sort every role in the menu instead of moving one",
+                    )
+                    .add_string_choice("role position", "position")
+                    .add_string_choice("name", "name"),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "appearance",
+                    "change how a menu's message looks",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to change",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "title",
+                        "the title shown above the roles",
+                    )
+                    .max_length(EMBED_TITLE_MAX_LENGTH),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "description",
+                        "text shown above the roles, where \\n starts a new line",
+                    )
+                    .max_length(EMBED_DESCRIPTION_MAX_LENGTH),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "color",
+                    "the color of the message's edge, as a hex code such as #5865F2",
+                ))
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "footer",
+                        "small text shown below the roles",
+                    )
+                    .max_length(EMBED_FOOTER_MAX_LENGTH),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "thumbnail",
+                    "the URL of a small image shown beside the roles",
+                ))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "image",
+                    "the URL of a large image shown below the roles",
+                ))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "reset",
+                    "whether to go back to the default look before applying the other options",
+                )),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "warnings",
+                "show and clear problems the bot noticed with the configuration",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "list",
+                "show every menu and its roles, along with any problems with them",
+            ))
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "sync",
+                    "grant and revoke roles to match the reactions on reaction menus",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to sync, or empty for every menu",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "limit",
+                    "cap how many roles from a menu a member may hold",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to change",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "max",
+                        "the most roles a member may hold, or empty for no limit",
+                    )
+                    .min_int_value(1)
+                    .max_int_value(u8::MAX.into()),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "requirement",
+                    "require or forbid another role for picking a self-service role",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu the role is in",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "role",
+                        "the self-service role to restrict",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "other-role",
+                        "the role members must or must not hold",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "kind",
+                        "how the other role affects picking the role",
+                    )
+                    .add_string_choice("required", "required")
+                    .add_string_choice("blocked", "blocked")
+                    .add_string_choice("none", "none")
+                    .required(true),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "group",
+                    "put a self-service role into a group within its menu",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu the role is in",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Role, "role", "the role to group")
+                        .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "group",
+                        "the group to put the role in, or empty to remove it from its group",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "exclusive",
+                    "allow members to hold only one role from a menu or group",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to change",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Boolean,
+                        "exclusive",
+                        "whether members may hold only one of the roles",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "group",
+                        "the group within the menu to change, or empty for the whole menu",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "duration",
+                    "take a role away again some time after members pick it",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu the role is in",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "role",
+                        "the role to make temporary",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "hours",
+                        "how many hours members keep the role",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(DURATION_MAX_HOURS),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "minutes",
+                        "how many minutes members keep the role, on top of the hours",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(59),
+                ),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "bundle",
+                    "give members another role along with one they pick",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu the role is in",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "role",
+                        "the role members pick",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Role,
+                        "other-role",
+                        "the role members get along with it",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "remove",
+                    "stop giving the other role along with it",
+                )),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "window",
+                    "only let members pick roles from a menu between two times",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "menu",
+                        "the role menu to schedule",
+                    )
+                    .max_length(MENU_NAME_MAX_LENGTH)
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "opens",
+                    "when the menu opens, as a Discord timestamp or a date like \
+                     2024-06-01T18:00:00Z",
+                ))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "closes",
+                    "when the menu closes, as a Discord timestamp or a date like \
+                     2024-06-01T18:00:00Z",
+                ))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "clear",
+                    "let members pick roles at any time again",
+                )),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "log",
+                    "post a record of self-service role changes to a channel",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Channel,
+                        "channel",
+                        "the channel to log to, or empty to stop logging",
+                    )
+                    .channel_types(vec![ChannelType::Text]),
                 ),
             ),
         )
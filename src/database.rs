@@ -1,26 +1,272 @@
-use std::sync::RwLock;
+mod pickle;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
 
 use log::error;
-use pickledb::PickleDb;
 use serenity::model::prelude::GuildId;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
 
 use crate::guild_data::GuildData;
 
-pub fn get_guild_data(db: &RwLock<PickleDb>, guild_id: GuildId) -> Option<GuildData> {
-    db.read()
-        .expect("The database lock is poisoned due to a panic on write")
-        .get::<GuildData>(&guild_id.to_string())
-}
-
-pub fn update_guild_data(db: &RwLock<PickleDb>, guild_id: GuildId, new_data: &GuildData) {
-    if let Err(e) = db
-        .write()
-        .expect("The database lock is poisoned due to a panic on write")
-        .set::<GuildData>(&guild_id.to_string(), new_data)
-    {
-        error!(
-            "Could not write guild data to database for guild {:?}: {}",
-            guild_id, e
+pub use pickle::PickleStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+pub type StorageError = Box<dyn Error + Send + Sync>;
+
+/// A persistent home for each guild's configuration
+pub trait Storage: Send + Sync {
+    /// Reads the guild's configuration, or `None` if it has never been stored. Data that exists
+    /// but cannot be read is an error, so it is never mistaken for an unconfigured guild.
+    fn load(&self, guild_id: GuildId) -> Result<Option<GuildData>, StorageError>;
+
+    fn store(&self, guild_id: GuildId, data: &GuildData) -> Result<(), StorageError>;
+}
+
+/// Serializes changes to each guild's configuration on top of a [`Storage`] backend
+pub struct Database {
+    storage: Box<dyn Storage>,
+    guild_locks: Mutex<HashMap<GuildId, Arc<AsyncMutex<()>>>>,
+}
+
+impl Database {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self {
+            storage,
+            guild_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads a snapshot of the guild's configuration, which may be stale by the time it is used.
+    /// Configuration that cannot be read is treated as missing.
+    pub fn get_guild_data(&self, guild_id: GuildId) -> Option<GuildData> {
+        self.storage.load(guild_id).unwrap_or_else(|e| {
+            error!("Could not read guild data for guild {:?}: {}", guild_id, e);
+            None
+        })
+    }
+
+    /// Starts a read-modify-write of the guild's configuration.
+    ///
+    /// Other transactions for the same guild wait until this one is committed or dropped, so the
+    /// transaction may be held across Discord API calls without losing concurrent changes. Fails
+    /// if the stored configuration cannot be read, since committing in its place would erase it.
+    pub async fn transaction(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<GuildTransaction<'_>, StorageError> {
+        let lock = self
+            .guild_locks
+            .lock()
+            .expect("The guild lock table is poisoned due to a panic on write")
+            .entry(guild_id)
+            .or_default()
+            .clone();
+        let guard = lock.lock_owned().await;
+
+        let data = self.storage.load(guild_id).map_err(|e| {
+            error!(
+                "Could not read guild data for guild {:?}, so it was left unchanged: {}",
+                guild_id, e
+            );
+            e
+        })?;
+
+        Ok(GuildTransaction {
+            db: self,
+            guild_id,
+            data: data.unwrap_or_default(),
+            _guard: guard,
+        })
+    }
+}
+
+/// Exclusive access to one guild's configuration, discarded unless committed
+pub struct GuildTransaction<'a> {
+    db: &'a Database,
+    guild_id: GuildId,
+    data: GuildData,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl GuildTransaction<'_> {
+    pub fn commit(self) {
+        if let Err(e) = self.db.storage.store(self.guild_id, &self.data) {
+            error!(
+                "Could not write guild data to database for guild {:?}: {}",
+                self.guild_id, e
+            );
+        }
+    }
+}
+
+impl Deref for GuildTransaction<'_> {
+    type Target = GuildData;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for GuildTransaction<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::channel::ReactionType;
+
+    use super::*;
+    use crate::role_menu::UnmetRequirement;
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        guilds: Mutex<HashMap<GuildId, String>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn load(&self, guild_id: GuildId) -> Result<Option<GuildData>, StorageError> {
+            self.guilds
+                .lock()
+                .unwrap()
+                .get(&guild_id)
+                .map(|json| serde_json::from_str(json))
+                .transpose()
+                .map_err(Into::into)
+        }
+
+        fn store(&self, guild_id: GuildId, data: &GuildData) -> Result<(), StorageError> {
+            self.guilds
+                .lock()
+                .unwrap()
+                .insert(guild_id, serde_json::to_string(data)?);
+            Ok(())
+        }
+    }
+
+    fn database() -> Arc<Database> {
+        Arc::new(Database::new(Box::<MemoryStorage>::default()))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_transactions_keep_every_change() {
+        let db = database();
+        let guild_id = GuildId::new(1);
+
+        let commands = (0..32).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let mut data = db.transaction(guild_id).await.unwrap();
+                // Stand-in for the Discord API calls a command makes before saving
+                tokio::task::yield_now().await;
+                data.get_or_create_menu(&format!("menu-{i}"));
+                data.commit();
+            })
+        });
+        for command in commands.collect::<Vec<_>>() {
+            command.await.unwrap();
+        }
+
+        let mut data = db.get_guild_data(guild_id).unwrap();
+        for i in 0..32 {
+            assert!(
+                data.get_menu_mut(&format!("menu-{i}")).is_some(),
+                "menu-{i} was lost"
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn transactions_on_different_guilds_do_not_wait_on_each_other() {
+        let db = database();
+
+        let first = db.transaction(GuildId::new(1)).await.unwrap();
+        let mut second = db.transaction(GuildId::new(2)).await.unwrap();
+        second.get_or_create_menu("games");
+        second.commit();
+        drop(first);
+
+        assert!(db.get_guild_data(GuildId::new(1)).is_none());
+        assert!(db
+            .get_guild_data(GuildId::new(2))
+            .is_some_and(|mut data| data.get_menu_mut("games").is_some()));
+    }
+
+    #[tokio::test]
+    async fn dropped_transaction_is_discarded() {
+        let db = database();
+        let guild_id = GuildId::new(1);
+
+        let mut data = db.transaction(guild_id).await.unwrap();
+        data.get_or_create_menu("games");
+        drop(data);
+
+        assert!(db.get_guild_data(guild_id).is_none());
+        assert!(db
+            .transaction(guild_id)
+            .await
+            .unwrap()
+            .get_menu_mut("games")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn configured_menus_survive_a_transaction() {
+        let storage = MemoryStorage::default();
+        let guild_id = GuildId::new(1);
+        storage.guilds.lock().unwrap().insert(
+            guild_id,
+            concat!(
+                r#"{"menus":{"games":{"channel_id":"1","message_id":"2","#,
+                r#""bundles":[{"emoji":{"name":"🎉"},"role_id":5,"extra_roles":[6]}],"#,
+                r#""requirements":{"5":{"required":[7],"blocked":[]}},"#,
+                r#""descriptions":{"5":"Board game nights"}}}}"#
+            )
+            .to_owned(),
+        );
+        let db = Database::new(Box::new(storage));
+
+        let mut data = db.transaction(guild_id).await.unwrap();
+        data.get_or_create_menu("pronouns");
+        data.commit();
+
+        let data = db.get_guild_data(guild_id).unwrap();
+        let menu = data.get_menu("games").unwrap();
+        let emoji = ReactionType::Unicode("🎉".to_owned());
+        assert_eq!(menu.get_role(&emoji), Some(&5));
+        assert_eq!(menu.get_bundle(5), vec![5, 6]);
+        assert!(matches!(
+            menu.check_requirements(&[], 5),
+            Some(UnmetRequirement::Missing(7))
+        ));
+        assert_eq!(
+            serde_json::to_value(menu).unwrap()["descriptions"]["5"],
+            "Board game nights"
         );
+        assert!(data.get_menu("pronouns").is_some());
+    }
+
+    #[tokio::test]
+    async fn unreadable_data_is_not_overwritten() {
+        let storage = MemoryStorage::default();
+        let guild_id = GuildId::new(1);
+        storage
+            .guilds
+            .lock()
+            .unwrap()
+            .insert(guild_id, "not guild data".to_owned());
+        let db = Database::new(Box::new(storage));
+
+        assert!(db.transaction(guild_id).await.is_err());
+        assert!(db.get_guild_data(guild_id).is_none());
     }
 }
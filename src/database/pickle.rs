@@ -0,0 +1,67 @@
+use std::{path::Path, sync::RwLock};
+
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use serenity::model::prelude::GuildId;
+
+use super::{Storage, StorageError};
+use crate::guild_data::GuildData;
+
+/// Stores all guilds in a single JSON file that is rewritten on every change
+pub struct PickleStorage {
+    db: RwLock<PickleDb>,
+}
+
+impl PickleStorage {
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let db = if path.exists() {
+            PickleDb::load_json(path, PickleDbDumpPolicy::AutoDump).expect("A valid database file")
+        } else {
+            PickleDb::new_json(path, PickleDbDumpPolicy::AutoDump)
+        };
+
+        Self {
+            db: RwLock::new(db),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl PickleStorage {
+    /// Every guild with stored configuration, for importing into another backend
+    pub fn get_guild_ids(&self) -> Vec<GuildId> {
+        self.db
+            .read()
+            .expect("The database lock is poisoned due to a panic on write")
+            .get_all()
+            .iter()
+            .filter_map(|key| key.parse().ok())
+            .map(GuildId::new)
+            .collect()
+    }
+}
+
+impl Storage for PickleStorage {
+    fn load(&self, guild_id: GuildId) -> Result<Option<GuildData>, StorageError> {
+        let db = self
+            .db
+            .read()
+            .expect("The database lock is poisoned due to a panic on write");
+        let key = guild_id.to_string();
+        // PickleDB hides why a value could not be read, but a stored value it cannot read is still
+        // an error rather than a missing one
+        match db.get::<GuildData>(&key) {
+            Some(data) => Ok(Some(data)),
+            None if db.exists(&key) => Err("the stored guild data could not be parsed".into()),
+            None => Ok(None),
+        }
+    }
+
+    fn store(&self, guild_id: GuildId, data: &GuildData) -> Result<(), StorageError> {
+        self.db
+            .write()
+            .expect("The database lock is poisoned due to a panic on write")
+            .set::<GuildData>(&guild_id.to_string(), data)
+            .map_err(Into::into)
+    }
+}
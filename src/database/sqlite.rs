@@ -0,0 +1,96 @@
+use std::{path::Path, sync::Mutex};
+
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::model::prelude::GuildId;
+
+use super::{PickleStorage, Storage, StorageError};
+use crate::guild_data::GuildData;
+
+/// Stores each guild as its own row, so a change only rewrites that guild's configuration
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let connection = Connection::open(path).expect("A valid database file");
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS guild_data (
+                    guild_id INTEGER PRIMARY KEY,
+                    data TEXT NOT NULL
+                )",
+                (),
+            )
+            .expect("The guild data table to be created");
+
+        Self {
+            connection: Mutex::new(connection),
+        }
+    }
+
+    /// Copies every guild from a PickleDB file while this database is still empty, so switching
+    /// backends keeps existing menus. The copy is all or nothing, so an interrupted import is
+    /// simply retried on the next start.
+    pub fn import_pickle(&self, path: impl AsRef<Path>) -> Result<(), StorageError> {
+        let path = path.as_ref();
+        let mut connection = self.connection();
+        let count: i64 =
+            connection.query_row("SELECT COUNT(*) FROM guild_data", (), |row| row.get(0))?;
+        if count > 0 || !path.exists() {
+            return Ok(());
+        }
+
+        let pickle = PickleStorage::open(path);
+        let transaction = connection.transaction()?;
+        let mut imported = 0;
+        for guild_id in pickle.get_guild_ids() {
+            let Some(data) = pickle.load(guild_id)? else {
+                continue;
+            };
+            transaction.execute(
+                "INSERT INTO guild_data (guild_id, data) VALUES (?1, ?2)",
+                params![guild_id.get() as i64, serde_json::to_string(&data)?],
+            )?;
+            imported += 1;
+        }
+        transaction.commit()?;
+        info!("Imported {imported} guilds from {}", path.display());
+        Ok(())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .expect("The database lock is poisoned due to a panic on write")
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self, guild_id: GuildId) -> Result<Option<GuildData>, StorageError> {
+        let data: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT data FROM guild_data WHERE guild_id = ?1",
+                params![guild_id.get() as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match data {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store(&self, guild_id: GuildId, data: &GuildData) -> Result<(), StorageError> {
+        let json = serde_json::to_string(data)?;
+        self.connection().execute(
+            "INSERT INTO guild_data (guild_id, data) VALUES (?1, ?2)
+                ON CONFLICT (guild_id) DO UPDATE SET data = excluded.data",
+            params![guild_id.get() as i64, json],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, str::FromStr, sync::RwLock};
+
+use emojis::SkinTone;
+use log::error;
+use serenity::{
+    model::{
+        channel::ReactionType,
+        guild::Emoji,
+        id::{EmojiId, GuildId},
+        misc::EmojiIdentifier,
+    },
+    prelude::Context,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Discord writes skin tones as a second shortcode, such as `:wave::skin-tone-2:`
+const SKIN_TONE_SHORTCODES: [(&str, SkinTone); 5] = [
+    ("skin-tone-1", SkinTone::Light),
+    ("skin-tone-2", SkinTone::MediumLight),
+    ("skin-tone-3", SkinTone::Medium),
+    ("skin-tone-4", SkinTone::MediumDark),
+    ("skin-tone-5", SkinTone::Dark),
+];
+
+/// Why text could not be read as a single Unicode emoji
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmojiError {
+    Empty,
+    NotAnEmoji,
+    SeveralEmoji,
+    UnknownShortcode,
+}
+
+impl EmojiError {
+    pub fn describe(&self, input: &str) -> String {
+        match self {
+            EmojiError::Empty => "Please give an emoji".to_owned(),
+            EmojiError::NotAnEmoji => format!("{input} is not an emoji"),
+            EmojiError::SeveralEmoji => format!("{input} is more than one emoji, pick just one"),
+            EmojiError::UnknownShortcode => format!("{input} is not a known emoji shortcode"),
+        }
+    }
+}
+
+/// Reads a single Unicode emoji, typed either as the emoji itself or as a `:shortcode:`.
+///
+/// Emoji such as flags, keycaps, skin tones and families span several code points, so the input
+/// is split into user-perceived characters before being checked against the emoji table. The
+/// fully qualified form is returned, since that is what Discord sends with reactions.
+pub fn parse_unicode_emoji(input: &str) -> Result<&'static str, EmojiError> {
+    let input = input.trim();
+    if let Some(shortcode) = input
+        .strip_prefix(':')
+        .and_then(|rest| rest.strip_suffix(':'))
+    {
+        return parse_shortcode(shortcode).ok_or(EmojiError::UnknownShortcode);
+    }
+
+    let mut graphemes = input.graphemes(true);
+    match (graphemes.next(), graphemes.next()) {
+        (None, _) => Err(EmojiError::Empty),
+        (Some(grapheme), None) => emojis::get(grapheme)
+            .map(emojis::Emoji::as_str)
+            .ok_or(EmojiError::NotAnEmoji),
+        (Some(_), Some(_)) if input.graphemes(true).all(|g| emojis::get(g).is_some()) => {
+            Err(EmojiError::SeveralEmoji)
+        }
+        (Some(_), Some(_)) => Err(EmojiError::NotAnEmoji),
+    }
+}
+
+/// Finds Unicode emoji with a shortcode containing the query, putting those that start with it
+/// first
+pub fn search_unicode_emoji(query: &str) -> impl Iterator<Item = &'static emojis::Emoji> + '_ {
+    let starts_with =
+        move |emoji: &emojis::Emoji| emoji.shortcodes().any(|code| code.starts_with(query));
+    emojis::iter()
+        .filter(move |emoji| starts_with(emoji))
+        .chain(emojis::iter().filter(move |emoji| {
+            !starts_with(emoji) && emoji.shortcodes().any(|code| code.contains(query))
+        }))
+}
+
+fn parse_shortcode(shortcode: &str) -> Option<&'static str> {
+    let (name, skin_tone) = match shortcode.split_once("::") {
+        Some((name, tone)) => {
+            let (_, skin_tone) = SKIN_TONE_SHORTCODES
+                .iter()
+                .find(|(code, _)| *code == tone)?;
+            (name, Some(*skin_tone))
+        }
+        None => (shortcode, None),
+    };
+    let emoji = emojis::get_by_shortcode(name)?;
+    match skin_tone {
+        Some(skin_tone) => emoji.with_skin_tone(skin_tone),
+        None => Some(emoji),
+    }
+    .map(emojis::Emoji::as_str)
+}
+
+/// Every custom emoji in the guilds the bot is in, kept current from gateway events so resolving an
+/// emoji never has to ask Discord
+#[derive(Default)]
+pub struct EmojiIndex {
+    guilds: RwLock<HashMap<GuildId, HashMap<EmojiId, Emoji>>>,
+}
+
+impl EmojiIndex {
+    /// Replaces the guild's emoji, returning whether any it had before are gone
+    pub fn set_guild_emoji(&self, guild_id: GuildId, emojis: HashMap<EmojiId, Emoji>) -> bool {
+        let mut guilds = self.guilds.write().expect("Emoji index lock poisoned");
+        let removed = guilds
+            .get(&guild_id)
+            .is_some_and(|previous| previous.keys().any(|id| !emojis.contains_key(id)));
+        guilds.insert(guild_id, emojis);
+        removed
+    }
+
+    pub fn remove_guild(&self, guild_id: GuildId) {
+        self.guilds
+            .write()
+            .expect("Emoji index lock poisoned")
+            .remove(&guild_id);
+    }
+
+    /// Lists a guild's own custom emoji, ordered by name
+    pub fn get_guild_emoji(&self, guild_id: GuildId) -> Vec<Emoji> {
+        let mut emojis: Vec<Emoji> = self
+            .guilds
+            .read()
+            .expect("Emoji index lock poisoned")
+            .get(&guild_id)
+            .map(|emojis| emojis.values().cloned().collect())
+            .unwrap_or_default();
+        emojis.sort_by(|a, b| a.name.cmp(&b.name));
+        emojis
+    }
+
+    pub fn get_reaction(&self, emoji_id: EmojiId) -> Option<ReactionType> {
+        self.find_by_id(emoji_id).map(|(_, emoji)| emoji.into())
+    }
+
+    pub fn contains(&self, emoji_id: EmojiId) -> bool {
+        self.guilds
+            .read()
+            .expect("Emoji index lock poisoned")
+            .values()
+            .any(|emojis| emojis.contains_key(&emoji_id))
+    }
+
+    /// Resolves what an admin typed into an emoji the bot can react with.
+    ///
+    /// Accepts Unicode emoji and their `:shortcode:`s, a custom emoji's full `<:name:id>` form, or
+    /// a custom emoji's bare name. Names are looked up in the invoking guild before any other.
+    pub async fn resolve(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        input: &str,
+    ) -> Result<ReactionType, String> {
+        let input = input.trim();
+        let (emoji_guild_id, emoji) = if input.starts_with('<') {
+            let identifier =
+                EmojiIdentifier::from_str(input).map_err(|_| format!("{input} is not an emoji"))?;
+            self.find_by_id(identifier.id).ok_or_else(|| {
+                format!("{input} is from a server the bot is not in, so the bot cannot use it")
+            })?
+        } else {
+            match parse_unicode_emoji(input) {
+                Ok(emoji) => return Ok(ReactionType::Unicode(emoji.to_owned())),
+                Err(e) => self
+                    .find_by_name(guild_id, input.trim_matches(':'))?
+                    .ok_or_else(|| e.describe(input))?,
+            }
+        };
+        check_usable(ctx, emoji_guild_id, &emoji).await?;
+        Ok(emoji.into())
+    }
+
+    fn find_by_id(&self, emoji_id: EmojiId) -> Option<(GuildId, Emoji)> {
+        self.guilds
+            .read()
+            .expect("Emoji index lock poisoned")
+            .iter()
+            .find_map(|(guild_id, emojis)| {
+                emojis
+                    .get(&emoji_id)
+                    .map(|emoji| (*guild_id, emoji.clone()))
+            })
+    }
+
+    fn find_by_name(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+    ) -> Result<Option<(GuildId, Emoji)>, String> {
+        let guilds = self.guilds.read().expect("Emoji index lock poisoned");
+        let named = |emojis: &HashMap<EmojiId, Emoji>| -> Vec<Emoji> {
+            emojis
+                .values()
+                .filter(|emoji| emoji.name == name)
+                .cloned()
+                .collect()
+        };
+        let mut matches: Vec<(GuildId, Emoji)> = guilds
+            .get(&guild_id)
+            .map(named)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|emoji| (guild_id, emoji))
+            .collect();
+        if matches.is_empty() {
+            matches = guilds
+                .iter()
+                .filter(|(other, _)| **other != guild_id)
+                .flat_map(|(other, emojis)| named(emojis).into_iter().map(|emoji| (*other, emoji)))
+                .collect();
+        }
+        if matches.len() > 1 {
+            return Err(format!(
+                "There are several emoji named {name}. Pick one by giving its full form, which \
+                 you can copy by sending \\:{name}: in Discord."
+            ));
+        }
+        Ok(matches.pop())
+    }
+}
+
+/// Checks whether the bot may use a custom emoji from the given guild
+async fn check_usable(ctx: &Context, guild_id: GuildId, emoji: &Emoji) -> Result<(), String> {
+    if !emoji.available {
+        return Err(format!(
+            "{emoji} cannot be used right now, as its server lost the Server Boost level that \
+             unlocked it"
+        ));
+    }
+    if !emoji.roles.is_empty() {
+        let bot_id = ctx.cache.current_user().id;
+        let bot = guild_id.member(ctx, bot_id).await.map_err(|e| {
+            error!(
+                "Could not fetch the bot's roles in guild {:?}: {:?}",
+                guild_id, e
+            );
+            format!("Could not check whether the bot may use {emoji}")
+        })?;
+        if !emoji
+            .roles
+            .iter()
+            .any(|role_id| bot.roles.contains(role_id))
+        {
+            return Err(format!(
+                "{emoji} is restricted to certain roles in its server, and the bot has none of \
+                 them"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_emoji() {
+        let cases = [
+            ("🎉", "🎉"),
+            (" 🎉 ", "🎉"),
+            // Multi-code-point sequences must survive intact
+            ("👨\u{200d}👩\u{200d}👧", "👨\u{200d}👩\u{200d}👧"),
+            ("🏳\u{fe0f}\u{200d}🌈", "🏳\u{fe0f}\u{200d}🌈"),
+            ("🇯🇵", "🇯🇵"),
+            (
+                "🏴\u{e0067}\u{e0062}\u{e0073}\u{e0063}\u{e0074}\u{e007f}",
+                "🏴\u{e0067}\u{e0062}\u{e0073}\u{e0063}\u{e0074}\u{e007f}",
+            ),
+            ("👍🏽", "👍🏽"),
+            ("1\u{fe0f}\u{20e3}", "1\u{fe0f}\u{20e3}"),
+            ("#\u{fe0f}\u{20e3}", "#\u{fe0f}\u{20e3}"),
+            // Text-presentation forms are stored fully qualified, as Discord sends them
+            ("❤", "❤\u{fe0f}"),
+            ("☺", "☺\u{fe0f}"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_unicode_emoji(input), Ok(expected), "input {input:?}");
+        }
+    }
+
+    #[test]
+    fn parses_shortcodes() {
+        let cases = [
+            (":tada:", "🎉"),
+            (":heart:", "❤\u{fe0f}"),
+            (":jp:", "🇯🇵"),
+            (":one:", "1\u{fe0f}\u{20e3}"),
+            (":+1::skin-tone-3:", "👍🏽"),
+            (":wave::skin-tone-1:", "👋🏻"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_unicode_emoji(input), Ok(expected), "input {input:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_non_emoji() {
+        let cases = [
+            ("", EmojiError::Empty),
+            ("   ", EmojiError::Empty),
+            ("a", EmojiError::NotAnEmoji),
+            ("abc", EmojiError::NotAnEmoji),
+            ("1", EmojiError::NotAnEmoji),
+            ("🎉a", EmojiError::NotAnEmoji),
+            ("🎉🎉", EmojiError::SeveralEmoji),
+            ("🇯🇵🇺🇸", EmojiError::SeveralEmoji),
+            ("👍 🎉", EmojiError::NotAnEmoji),
+            (":not-an-emoji:", EmojiError::UnknownShortcode),
+            (":tada::skin-tone-9:", EmojiError::UnknownShortcode),
+            (":tada::skin-tone-1:", EmojiError::UnknownShortcode),
+            ("::", EmojiError::UnknownShortcode),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_unicode_emoji(input), Err(expected), "input {input:?}");
+        }
+    }
+
+    #[test]
+    fn searches_shortcodes_by_prefix_first() {
+        let results: Vec<&str> = search_unicode_emoji("heart").map(|e| e.as_str()).collect();
+        let position = |emoji: &str| results.iter().position(|result| *result == emoji);
+        let broken_heart = position("💔").expect("broken_heart contains the query");
+        assert!(position("❤\u{fe0f}").is_some_and(|heart| heart < broken_heart));
+        assert!(position("😍").is_some_and(|heart_eyes| heart_eyes < broken_heart));
+        assert!(search_unicode_emoji("not-an-emoji").next().is_none());
+    }
+}
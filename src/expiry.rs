@@ -0,0 +1,162 @@
+use std::{sync::Arc, time::Duration};
+
+use log::error;
+use serenity::{
+    model::{
+        id::{GuildId, RoleId, UserId},
+        Timestamp,
+    },
+    prelude::Context,
+};
+
+use crate::{
+    database::Database,
+    role_menu::RoleMenu,
+    self_service::{remove_member_reaction, revoke_roles},
+    util::is_unknown_member,
+};
+
+/// How often the bot looks for temporary roles that have run out
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Spells out a temporary role's duration, such as "1 hour 30 minutes"
+pub fn describe_duration(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = seconds % 3600 / 60;
+    let unit = |count: u64, name: &str| match count {
+        1 => format!("1 {name}"),
+        count => format!("{count} {name}s"),
+    };
+    match (hours, minutes) {
+        (0, minutes) => unit(minutes, "minute"),
+        (hours, 0) => unit(hours, "hour"),
+        (hours, minutes) => format!("{} {}", unit(hours, "hour"), unit(minutes, "minute")),
+    }
+}
+
+/// Notes when a temporary role just granted from the menu should be taken away again
+pub async fn schedule_expiry(
+    db: &Database,
+    guild_id: GuildId,
+    menu: &RoleMenu,
+    user_id: UserId,
+    role_id: u64,
+) {
+    let Some(seconds) = menu.get_duration(role_id) else {
+        return;
+    };
+    let expires_at = i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| Timestamp::now().unix_timestamp().checked_add(seconds))
+        .and_then(|time| Timestamp::from_unix_timestamp(time).ok());
+    match expires_at {
+        Some(expires_at) => {
+            if let Ok(mut data) = db.transaction(guild_id).await {
+                data.schedule_expiration(role_id, user_id, expires_at);
+                data.commit();
+            }
+        }
+        None => error!(
+            "Could not schedule the expiry of role {:?} for user {:?}",
+            role_id, user_id
+        ),
+    }
+}
+
+/// Forgets when a temporary role runs out once the member gives it up, so the old timer cannot
+/// take the role away after it was granted again some other way
+pub async fn cancel_expiry(db: &Database, guild_id: GuildId, user_id: UserId, role_id: u64) {
+    if !db
+        .get_guild_data(guild_id)
+        .is_some_and(|data| data.has_expiration(role_id, user_id))
+    {
+        return;
+    }
+    if let Ok(mut data) = db.transaction(guild_id).await {
+        data.cancel_expiration(role_id, user_id);
+        data.commit();
+    }
+}
+
+/// Takes away temporary roles as they run out, for as long as the bot runs. Expirations are
+/// stored with each guild's data, so roles that ran out while the bot was offline are taken away
+/// on the first check after it starts.
+pub async fn expire_roles_periodically(ctx: Context, db: Arc<Database>) {
+    let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for guild_id in ctx.cache.guilds() {
+            expire_roles(&ctx, &db, guild_id).await;
+        }
+    }
+}
+
+/// Takes the guild's expired temporary roles away from their members, along with the members'
+/// reactions for them. Roles that could not be taken away are tried again on the next check.
+async fn expire_roles(ctx: &Context, db: &Database, guild_id: GuildId) {
+    let now = Timestamp::now();
+    if !db
+        .get_guild_data(guild_id)
+        .is_some_and(|data| data.has_expired_roles(now))
+    {
+        return;
+    }
+
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    let audit_log = data.get_audit_log();
+    let mut warnings = Vec::new();
+    let mut retries = Vec::new();
+    for mut expiration in data.take_expired_roles(now) {
+        let role_id = expiration.get_role_id();
+        let user_id = expiration.get_user_id();
+        let member = match guild_id.member(ctx, user_id).await {
+            Ok(member) => member,
+            // Members who left the guild took the role with them
+            Err(e) if is_unknown_member(&e) => continue,
+            Err(e) => {
+                error!("Could not remove role from user {:?}: {:?}", user_id, e);
+                retries.push(expiration);
+                continue;
+            }
+        };
+        // Members may have given the role up themselves in the meantime
+        if !member.roles.contains(&RoleId::new(role_id)) {
+            continue;
+        }
+
+        // Roles bundled with the expired one go too, unless another of the member's picks grants
+        // them
+        let result = match data.get_menus().find(|(_, menu)| menu.has_role(role_id)) {
+            Some((_, menu)) => {
+                let roles = data.get_roles_to_revoke(menu, role_id, &member.roles);
+                revoke_roles(ctx, guild_id, user_id, &roles, None).await
+            }
+            None => member.remove_role(ctx, role_id).await,
+        };
+        if let Err(e) = result {
+            error!("Could not remove role from user {:?}: {:?}", user_id, e);
+            // The bot keeps trying every check, which is only worth warning about once
+            if expiration.note_failure() {
+                warnings.push(format!(
+                    "Could not take the expired <@&{role_id}> role away from <@{user_id}>, the bot \
+                     will keep trying"
+                ));
+            }
+            retries.push(expiration);
+            continue;
+        }
+        audit_log.role_expired(ctx, user_id, role_id).await;
+        for (_, menu) in data.get_menus().filter(|(_, menu)| menu.has_role(role_id)) {
+            remove_member_reaction(ctx, menu, user_id, role_id).await;
+        }
+    }
+    for expiration in retries {
+        data.retry_expiration(expiration);
+    }
+    for warning in warnings {
+        data.add_warning(warning);
+    }
+    data.commit();
+}
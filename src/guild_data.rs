@@ -1,193 +1,322 @@
-use std::fmt::Write;
+use std::collections::{BTreeMap, BTreeSet};
 
 use bimap::BiMap;
-use log::error;
 use serde::{Deserialize, Serialize};
-use serenity::{
-    all::{CreateEmbed, CreateMessage, EditMessage},
-    futures::TryFutureExt,
-    model::{
-        prelude::{ChannelId, MessageId, ReactionType},
-        Color,
-    },
-    prelude::Context,
+use serenity::model::{
+    prelude::{ChannelId, MessageId, ReactionType, RoleId, UserId},
+    Timestamp,
 };
 
+use crate::{audit_log::AuditLog, role_menu::RoleMenu};
+
+/// The name given to the single menu of guilds configured before named menus existed
+const LEGACY_MENU_NAME: &str = "default";
+
+/// Only the most recent warnings are kept so a noisy guild cannot grow its data without bound
+const MAX_WARNINGS: usize = 25;
+
+/// A problem with the configuration noticed by the bot, kept until an admin reviews it
+#[derive(Serialize, Deserialize)]
+pub struct Warning {
+    time: Timestamp,
+    message: String,
+}
+
+impl Warning {
+    pub fn get_time(&self) -> Timestamp {
+        self.time
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// When a member's temporary role is due to be taken away
 #[derive(Serialize, Deserialize)]
+pub struct Expiration {
+    role_id: u64,
+    user_id: UserId,
+    expires_at: Timestamp,
+    /// Whether admins were already warned that taking the role away failed
+    #[serde(default)]
+    warned: bool,
+}
+
+impl Expiration {
+    pub fn get_role_id(&self) -> u64 {
+        self.role_id
+    }
+
+    pub fn get_user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    /// Notes that taking the role away failed, returning whether this is the first time
+    pub fn note_failure(&mut self) -> bool {
+        !std::mem::replace(&mut self.warned, true)
+    }
+
+    fn is_due(&self, now: Timestamp) -> bool {
+        self.expires_at.unix_timestamp() <= now.unix_timestamp()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(from = "StoredGuildData")]
 pub struct GuildData {
-    channel_id: Option<ChannelId>,
-    message_id: Option<MessageId>,
-    roles_to_emoji: BiMap<u64, ReactionType>,
+    menus: BTreeMap<String, RoleMenu>,
+    warnings: Vec<Warning>,
+    log_channel_id: Option<ChannelId>,
+    expirations: Vec<Expiration>,
 }
 
 impl GuildData {
-    pub fn new(roles_to_emoji: BiMap<u64, ReactionType>) -> Self {
-        Self {
-            channel_id: None,
-            message_id: None,
-            roles_to_emoji,
-        }
+    pub fn get_menus(&self) -> impl Iterator<Item = (&String, &RoleMenu)> {
+        self.menus.iter()
     }
 
-    pub async fn send_message(&mut self, ctx: &Context, channel_id: ChannelId) -> &Self {
-        if !self.message_exists(ctx, channel_id).await {
-            let message_id = channel_id
-                .send_message(ctx, {
-                    let result =
-                        CreateMessage::new().reactions(self.roles_to_emoji.right_values().cloned());
-                    let message = self.generate_message();
-
-                    if message.is_empty() {
-                        result
-                            .embeds(Vec::new())
-                            .content("No configured roles to display")
-                    } else {
-                        result
-                            .embed(CreateEmbed::new().color(Color::DARKER_GREY).field(
-                                "Self-Assignable Roles",
-                                message,
-                                true,
-                            ))
-                            .content("")
-                    }
-                })
-                .await
-                .map(|msg| msg.id);
-
-            if message_id.is_err() {
-                error!("Could not send message: {:?}", message_id);
-            } else {
-                self.channel_id = Some(channel_id);
-            }
+    pub fn get_menu(&self, name: &str) -> Option<&RoleMenu> {
+        self.menus.get(name)
+    }
 
-            self.message_id = message_id.ok();
-        }
-        self
-    }
-
-    pub async fn add_role(&mut self, ctx: &Context, role_id: u64, emoji: ReactionType) {
-        self.roles_to_emoji.insert(role_id, emoji.clone());
-        self.update_message(ctx, Some(emoji), false).await;
-    }
-
-    pub async fn remove_role(&mut self, ctx: &Context, role_id: u64) {
-        let emoji = self
-            .roles_to_emoji
-            .remove_by_left(&role_id)
-            .map(|(_, emoji)| emoji);
-        self.update_message(ctx, emoji, true).await;
-    }
-
-    pub fn get_role(&self, emoji: &ReactionType) -> Option<&u64> {
-        self.roles_to_emoji.get_by_right(emoji)
-    }
-
-    pub fn get_message_id(&self) -> Option<MessageId> {
-        self.message_id
-    }
-
-    async fn update_message(&self, ctx: &Context, maybe_emoji: Option<ReactionType>, remove: bool) {
-        if let (Some(channel_id), Some(message_id)) = (self.channel_id, self.message_id) {
-            if let Err(e) = channel_id
-                .edit_message(ctx, message_id, {
-                    let result = EditMessage::new();
-                    let message = self.generate_message();
-
-                    if message.is_empty() {
-                        result
-                            .embeds(Vec::new())
-                            .content("No configured roles to display")
-                    } else {
-                        result
-                            .embed(CreateEmbed::new().color(Color::DARKER_GREY).field(
-                                "Self-Assignable Roles",
-                                message,
-                                true,
-                            ))
-                            .content("")
-                    }
-                })
-                .await
-            {
-                error!(
-                    "Could not edit message for channel {:?}: {:?}",
-                    self.channel_id, e
-                );
-                return;
-            }
+    pub fn get_menu_mut(&mut self, name: &str) -> Option<&mut RoleMenu> {
+        self.menus.get_mut(name)
+    }
 
-            match maybe_emoji {
-                Some(emoji) if remove => {
-                    if let Err(e) = channel_id
-                        .message(ctx, message_id)
-                        .and_then(|message| async move {
-                            message.delete_reaction_emoji(ctx, emoji).await
-                        })
-                        .await
-                    {
-                        error!(
-                            "Could not remove reactions to message for channel {:?}: {:?}",
-                            self.channel_id, e
-                        );
-                    }
-                }
-                Some(emoji) => {
-                    if let Err(e) = channel_id.create_reaction(ctx, message_id, emoji).await {
-                        error!(
-                            "Could not react to message for channel {:?}: {:?}",
-                            self.channel_id, e
-                        );
-                    }
-                }
-                None => {}
-            }
-        }
+    /// Gets the named menu, creating an empty one if it does not exist yet
+    pub fn get_or_create_menu(&mut self, name: &str) -> &mut RoleMenu {
+        self.menus.entry(name.to_owned()).or_default()
+    }
+
+    pub fn get_menus_mut(&mut self) -> impl Iterator<Item = (&String, &mut RoleMenu)> {
+        self.menus.iter_mut()
+    }
+
+    /// Every role the guild's other menus hand out, along with the roles bundled with them
+    pub fn get_roles_from_other_menus(&self, name: &str) -> BTreeSet<u64> {
+        self.menus
+            .iter()
+            .filter(|(other, _)| *other != name)
+            .flat_map(|(_, menu)| menu.get_granted_role_ids())
+            .collect()
+    }
+
+    /// Every role the member's picks from the guild's menus grant, along with the roles bundled
+    /// with them
+    pub fn get_granted_roles(&self, picked: &[RoleId]) -> BTreeSet<u64> {
+        self.menus
+            .values()
+            .flat_map(|menu| {
+                menu.get_role_ids()
+                    .into_iter()
+                    .filter(|role_id| picked.contains(&RoleId::new(*role_id)))
+                    .flat_map(move |role_id| menu.get_bundle(role_id))
+            })
+            .collect()
+    }
+
+    /// The roles to take away along with the role, leaving those that another of the member's
+    /// picks from any of the guild's menus still grants
+    pub fn get_roles_to_revoke(
+        &self,
+        menu: &RoleMenu,
+        role_id: u64,
+        picked: &[RoleId],
+    ) -> Vec<u64> {
+        let others: Vec<RoleId> = picked
+            .iter()
+            .copied()
+            .filter(|other| other.get() != role_id)
+            .collect();
+        let kept = self.get_granted_roles(&others);
+        menu.get_bundle(role_id)
+            .into_iter()
+            .filter(|role| !kept.contains(role))
+            .collect()
+    }
+
+    pub fn find_menu_by_message(&self, message_id: MessageId) -> Option<&RoleMenu> {
+        self.menus
+            .values()
+            .find(|menu| menu.has_message(message_id))
+    }
+
+    pub fn set_log_channel(&mut self, channel_id: Option<ChannelId>) {
+        self.log_channel_id = channel_id;
     }
 
-    async fn message_exists(&self, ctx: &Context, channel_id: ChannelId) -> bool {
-        match self.message_id {
-            Some(message_id) => ctx
-                .http
-                .get_message(channel_id, message_id)
-                .await
-                .ok()
-                .is_some(),
-            None => false,
+    pub fn get_audit_log(&self) -> AuditLog {
+        AuditLog::new(self.log_channel_id)
+    }
+
+    /// Notes when the member's temporary role runs out, replacing any earlier time for it
+    pub fn schedule_expiration(&mut self, role_id: u64, user_id: UserId, expires_at: Timestamp) {
+        self.expirations
+            .retain(|expiration| expiration.role_id != role_id || expiration.user_id != user_id);
+        self.expirations.push(Expiration {
+            role_id,
+            user_id,
+            expires_at,
+            warned: false,
+        });
+    }
+
+    pub fn has_expiration(&self, role_id: u64, user_id: UserId) -> bool {
+        self.expirations
+            .iter()
+            .any(|expiration| expiration.role_id == role_id && expiration.user_id == user_id)
+    }
+
+    /// Forgets when the member's temporary role runs out, once they no longer hold it
+    pub fn cancel_expiration(&mut self, role_id: u64, user_id: UserId) {
+        self.expirations
+            .retain(|expiration| expiration.role_id != role_id || expiration.user_id != user_id);
+    }
+
+    pub fn has_expired_roles(&self, now: Timestamp) -> bool {
+        self.expirations
+            .iter()
+            .any(|expiration| expiration.is_due(now))
+    }
+
+    /// Removes the expirations that are due, for their roles to be taken away
+    pub fn take_expired_roles(&mut self, now: Timestamp) -> Vec<Expiration> {
+        let (expired, pending) = std::mem::take(&mut self.expirations)
+            .into_iter()
+            .partition(|expiration| expiration.is_due(now));
+        self.expirations = pending;
+        expired
+    }
+
+    /// Puts back an expiration whose role could not be taken away yet, to try again later
+    pub fn retry_expiration(&mut self, expiration: Expiration) {
+        self.expirations.push(expiration);
+    }
+
+    /// Drops the pending expirations of a role that no longer exists
+    pub fn forget_expiring_role(&mut self, role_id: u64) {
+        self.expirations
+            .retain(|expiration| expiration.role_id != role_id);
+    }
+
+    pub fn add_warning(&mut self, message: String) {
+        if self.warnings.len() >= MAX_WARNINGS {
+            self.warnings.remove(0);
         }
+        self.warnings.push(Warning {
+            time: Timestamp::now(),
+            message,
+        });
     }
 
-    fn generate_message(&self) -> String {
-        let mut result = String::new();
-
-        self.roles_to_emoji.iter().for_each(|entry| match entry.1 {
-            ReactionType::Custom { animated, id, name } => {
-                if *animated {
-                    writeln!(
-                        result,
-                        "<@&{}>: <a:{}:{}>",
-                        entry.0,
-                        name.as_ref().expect("A named emoji"),
-                        id
-                    )
-                    .expect("String concatenation success");
-                } else {
-                    writeln!(
-                        result,
-                        "<@&{}>: <:{}:{}>",
-                        entry.0,
-                        name.as_ref().expect("A named emoji"),
-                        id
-                    )
-                    .expect("String concatenation success");
-                }
+    pub fn get_warning_count(&self) -> usize {
+        self.warnings.len()
+    }
+
+    /// The recorded warnings, oldest first
+    pub fn get_warnings(&self) -> impl Iterator<Item = &Warning> {
+        self.warnings.iter()
+    }
+
+    /// Removes the oldest warnings once they have been shown to an admin
+    pub fn dismiss_warnings(&mut self, count: usize) {
+        self.warnings.drain(..count.min(self.warnings.len()));
+    }
+}
+
+/// The stored form of [`GuildData`], which also reads guilds configured before named menus existed,
+/// whose single menu's fields were stored in place of the menus.
+///
+/// The two forms are told apart by their fields rather than with an untagged enum, as untagged
+/// enums buffer the data first and can then no longer read the role IDs used as map keys.
+#[derive(Deserialize)]
+struct StoredGuildData {
+    menus: Option<BTreeMap<String, RoleMenu>>,
+    #[serde(default)]
+    warnings: Vec<Warning>,
+    #[serde(default)]
+    log_channel_id: Option<ChannelId>,
+    #[serde(default)]
+    expirations: Vec<Expiration>,
+    #[serde(default)]
+    channel_id: Option<ChannelId>,
+    #[serde(default)]
+    message_id: Option<MessageId>,
+    #[serde(default)]
+    roles_to_emoji: Option<BiMap<u64, ReactionType>>,
+}
+
+impl From<StoredGuildData> for GuildData {
+    fn from(stored: StoredGuildData) -> Self {
+        let menus = match (stored.menus, stored.roles_to_emoji) {
+            (Some(mut menus), _) => {
+                menus
+                    .values_mut()
+                    .for_each(RoleMenu::migrate_roles_to_emoji);
+                menus
             }
-            ReactionType::Unicode(char) => {
-                writeln!(result, "<@&{}>: {}", entry.0, char)
-                    .expect("String concatenation success");
+            (None, Some(roles_to_emoji)) => {
+                let menu =
+                    RoleMenu::from_legacy(stored.channel_id, stored.message_id, roles_to_emoji);
+                BTreeMap::from([(LEGACY_MENU_NAME.to_owned(), menu)])
             }
-            kind => error!("Unknown reaction {kind}, Discord may have made API changes"),
-        });
+            (None, None) => BTreeMap::new(),
+        };
+        Self {
+            menus,
+            warnings: stored.warnings,
+            log_channel_id: stored.log_channel_id,
+            expirations: stored.expirations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How guilds were stored before named menus existed
+    const LEGACY_JSON: &str =
+        r#"{"channel_id":"1","message_id":"2","roles_to_emoji":{"5":{"name":"🎉"}}}"#;
+
+    fn party() -> ReactionType {
+        ReactionType::Unicode("🎉".to_owned())
+    }
+
+    #[test]
+    fn legacy_data_becomes_the_default_menu() {
+        let data: GuildData = serde_json::from_str(LEGACY_JSON).unwrap();
+
+        let menu = data.get_menu(LEGACY_MENU_NAME).unwrap();
+        assert_eq!(menu.get_channel_id(), Some(ChannelId::new(1)));
+        assert_eq!(menu.get_message_id(), Some(MessageId::new(2)));
+        assert_eq!(menu.get_role(&party()), Some(&5));
+    }
+
+    #[test]
+    fn menus_with_roles_survive_a_round_trip() {
+        let data: GuildData = serde_json::from_str(LEGACY_JSON).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let data: GuildData = serde_json::from_str(&json).unwrap();
+
+        let menu = data.get_menu(LEGACY_MENU_NAME).unwrap();
+        assert_eq!(menu.get_role(&party()), Some(&5));
+        assert_eq!(menu.get_emoji(5), Some(&party()));
+    }
+
+    #[test]
+    fn roles_mapped_to_emoji_become_bundles() {
+        let json = concat!(
+            r#"{"menus":{"games":{"channel_id":null,"message_id":null,"#,
+            r#""roles_to_emoji":{"5":{"name":"🎉"}}}}}"#
+        );
+        let data: GuildData = serde_json::from_str(json).unwrap();
+        let stored = serde_json::to_value(&data).unwrap();
 
-        result
+        let menu = data.get_menu("games").unwrap();
+        assert_eq!(menu.get_role(&party()), Some(&5));
+        assert_eq!(menu.get_bundle(5), vec![5]);
+        assert!(stored["menus"]["games"].get("roles_to_emoji").is_none());
     }
 }
@@ -1,15 +1,21 @@
-use std::sync::RwLock;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use log::{error, warn};
-use pickledb::PickleDb;
 use serenity::{
     async_trait,
     client::{Context, EventHandler},
-    futures::TryFutureExt,
     model::{
-        application::{CommandDataOptionValue, Interaction},
-        channel::Reaction,
+        application::Interaction,
+        channel::{GuildChannel, Message, Reaction},
         gateway::Ready,
+        guild::{Emoji, Guild, Role, UnavailableGuild},
+        id::{ChannelId, EmojiId, GuildId, MessageId, RoleId},
     },
 };
 
@@ -18,20 +24,41 @@ use crate::commands::create;
 #[cfg(debug_assertions)]
 use crate::commands::create_for_test_guild;
 use crate::{
-    database::get_guild_data,
-    role_management::{create_message, disable_role, enable_role},
+    cleanup::{
+        forget_deleted_channel, forget_deleted_messages, prune_deleted_emoji, prune_deleted_role,
+    },
+    database::Database,
+    emoji::EmojiIndex,
+    expiry::expire_roles_periodically,
+    role_management::{
+        confirm_enable_role, create_message, describe_role, disable_role, enable_role, list_menus,
+        move_menu, reorder_roles, repost_menu, set_appearance, set_bundle, set_duration,
+        set_exclusive, set_group, set_limit, set_log_channel, set_requirement, set_style,
+        set_window, show_warnings, suggest_emoji, sync_reactions, REPLACE_ROLE_PREFIX,
+    },
+    role_menu::{MenuStyle, SELECT_ROLES_ID, TOGGLE_ROLE_PREFIX},
+    schedule::update_windows_periodically,
+    self_service::{add_reaction_role, remove_reaction_role, select_roles, toggle_role},
+    sync::sync_guild,
+    util::get_subcommand,
 };
 #[cfg(not(debug_assertions))]
 use serenity::model::application::Command;
 
 pub struct Handler {
-    db: RwLock<PickleDb>,
+    db: Arc<Database>,
+    emoji_index: EmojiIndex,
+    /// Ready fires again after reconnecting, but the background tasks and the catch-up sync should
+    /// only run once
+    background_tasks_started: AtomicBool,
 }
 
 impl Handler {
-    pub fn new(db: PickleDb) -> Self {
+    pub fn new(db: Database) -> Self {
         Self {
-            db: RwLock::new(db),
+            db: Arc::new(db),
+            emoji_index: EmojiIndex::default(),
+            background_tasks_started: AtomicBool::new(false),
         }
     }
 }
@@ -39,34 +66,96 @@ impl Handler {
 #[async_trait]
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            match command
-                .data
-                .options
-                .first()
-                .and_then(|opt| match &opt.value {
-                    CommandDataOptionValue::SubCommandGroup(group)
-                        if command.data.name == "role" && opt.name == "self-service" =>
-                    {
-                        group.first()
-                    }
-                    _ => None,
-                }) {
-                Some(opt) if opt.name == "enable" => {
-                    enable_role(&ctx, &self.db, &command, opt).await;
-                }
-                Some(opt) if opt.name == "disable" => {
-                    disable_role(&ctx, &self.db, &command, opt).await;
+        match interaction {
+            Interaction::Command(command) => {
+                match get_subcommand(&command) {
+                    Some(opt) if opt.name == "enable" => {
+                        enable_role(&ctx, &self.db, &self.emoji_index, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "disable" => {
+                        disable_role(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "message" => {
+                        create_message(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "move" => {
+                        move_menu(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "repost" => {
+                        repost_menu(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "style" => {
+                        set_style(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "describe" => {
+                        describe_role(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "reorder" => {
+                        reorder_roles(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "appearance" => {
+                        set_appearance(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "warnings" => {
+                        show_warnings(&ctx, &self.db, &command).await;
+                    }
+                    Some(opt) if opt.name == "list" => {
+                        list_menus(&ctx, &self.db, &self.emoji_index, &command).await;
+                    }
+                    Some(opt) if opt.name == "sync" => {
+                        sync_reactions(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "limit" => {
+                        set_limit(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "requirement" => {
+                        set_requirement(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "group" => {
+                        set_group(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "exclusive" => {
+                        set_exclusive(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "duration" => {
+                        set_duration(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "bundle" => {
+                        set_bundle(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "window" => {
+                        set_window(&ctx, &self.db, &command, opt).await;
+                    }
+                    Some(opt) if opt.name == "log" => {
+                        set_log_channel(&ctx, &self.db, &command, opt).await;
+                    }
+                    _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
                 }
-                Some(opt) if opt.name == "message" => {
-                    create_message(&ctx, &self.db, &command, opt).await;
+            }
+            Interaction::Autocomplete(command) => match get_subcommand(&command) {
+                Some(opt) if opt.name == "enable" => {
+                    suggest_emoji(&ctx, &self.db, &self.emoji_index, &command, opt).await;
                 }
-                _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+                _ => warn!("Autocomplete was requested for an unexpected option"),
+            },
+            Interaction::Component(component)
+                if component.data.custom_id.starts_with(TOGGLE_ROLE_PREFIX) =>
+            {
+                toggle_role(&ctx, &self.db, &component).await;
             }
+            Interaction::Component(component) if component.data.custom_id == SELECT_ROLES_ID => {
+                select_roles(&ctx, &self.db, &component).await;
+            }
+            Interaction::Component(component)
+                if component.data.custom_id.starts_with(REPLACE_ROLE_PREFIX) =>
+            {
+                confirm_enable_role(&ctx, &self.db, &self.emoji_index, &component).await;
+            }
+            _ => {}
         }
     }
 
-    async fn ready(&self, ctx: Context, _ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         // Discord's SLA for updating global commands is 1 hour
         // For better iteration, debug builds update a provided debug guild directly.
         #[cfg(debug_assertions)]
@@ -78,6 +167,90 @@ impl EventHandler for Handler {
         if let Err(e) = result {
             error!("Failed to create app command: {}", e);
         }
+
+        if !self.background_tasks_started.swap(true, Ordering::Relaxed) {
+            tokio::spawn(expire_roles_periodically(ctx.clone(), self.db.clone()));
+            tokio::spawn(update_windows_periodically(ctx.clone(), self.db.clone()));
+
+            // Catch up on reactions added while the bot was offline. Reconnects are left to admins
+            // to sync rather than repeating the catch-up on every one
+            for guild in ready.guilds {
+                sync_guild(&ctx, &self.db, guild.id).await;
+            }
+        }
+    }
+
+    async fn guild_role_delete(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        removed_role_id: RoleId,
+        _removed_role_data_if_available: Option<Role>,
+    ) {
+        prune_deleted_role(&ctx, &self.db, guild_id, removed_role_id).await;
+    }
+
+    async fn guild_emojis_update(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        current_state: HashMap<EmojiId, Emoji>,
+    ) {
+        if self.emoji_index.set_guild_emoji(guild_id, current_state) {
+            // Menus may use emoji from any guild the bot is in, so every guild's menus are checked
+            for menu_guild_id in ctx.cache.guilds() {
+                prune_deleted_emoji(&ctx, &self.db, &self.emoji_index, menu_guild_id).await;
+            }
+        }
+    }
+
+    async fn guild_create(&self, _ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        self.emoji_index.set_guild_emoji(guild.id, guild.emojis);
+    }
+
+    async fn guild_delete(
+        &self,
+        _ctx: Context,
+        incomplete: UnavailableGuild,
+        _full: Option<Guild>,
+    ) {
+        // Guilds also become unavailable during outages, when the bot keeps its membership
+        if !incomplete.unavailable {
+            self.emoji_index.remove_guild(incomplete.id);
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        if let Some(guild_id) = guild_id {
+            forget_deleted_messages(&ctx, &self.db, guild_id, &[deleted_message_id]).await;
+        }
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        ctx: Context,
+        _channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        guild_id: Option<GuildId>,
+    ) {
+        if let Some(guild_id) = guild_id {
+            forget_deleted_messages(&ctx, &self.db, guild_id, &multiple_deleted_messages_ids).await;
+        }
+    }
+
+    async fn channel_delete(
+        &self,
+        _ctx: Context,
+        channel: GuildChannel,
+        _messages: Option<Vec<Message>>,
+    ) {
+        forget_deleted_channel(&self.db, channel.guild_id, channel.id).await;
     }
 
     async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
@@ -86,20 +259,16 @@ impl EventHandler for Handler {
             (add_reaction.guild_id, add_reaction.user_id, bot_user)
         {
             if user_id != bot_id {
-                if let Some(role_id) = get_guild_data(&self.db, guild_id)
-                    .filter(|data| {
-                        data.get_message_id()
-                            .is_some_and(|message| add_reaction.message_id == message)
-                    })
-                    .and_then(|data| data.get_role(&add_reaction.emoji).copied())
-                {
-                    if let Err(e) = guild_id
-                        .member(ctx.clone(), user_id)
-                        .and_then(|member| async move { member.add_role(&ctx, role_id).await })
-                        .await
-                    {
-                        error!("Could not add role to user {:?}: {:?}", user_id, e);
-                    }
+                let guild_data = self.db.get_guild_data(guild_id);
+                if let Some((data, menu, role_id)) = guild_data.as_ref().and_then(|data| {
+                    data.find_menu_by_message(add_reaction.message_id)
+                        .filter(|menu| menu.get_style() == MenuStyle::Reactions)
+                        .and_then(|menu| {
+                            menu.get_role(&add_reaction.emoji)
+                                .map(|role_id| (data, menu, *role_id))
+                        })
+                }) {
+                    add_reaction_role(&ctx, &self.db, data, menu, guild_id, user_id, role_id).await;
                 }
             }
         }
@@ -113,20 +282,17 @@ impl EventHandler for Handler {
             bot_user,
         ) {
             if user_id != bot_id {
-                if let Some(role_id) = get_guild_data(&self.db, guild_id)
-                    .filter(|data| {
-                        data.get_message_id()
-                            .is_some_and(|message| removed_reaction.message_id == message)
-                    })
-                    .and_then(|data| data.get_role(&removed_reaction.emoji).copied())
-                {
-                    if let Err(e) = guild_id
-                        .member(ctx.clone(), user_id)
-                        .and_then(|member| async move { member.remove_role(&ctx, role_id).await })
-                        .await
-                    {
-                        error!("Could not remove role from user {:?}: {:?}", user_id, e);
-                    }
+                let guild_data = self.db.get_guild_data(guild_id);
+                if let Some((data, menu, role_id)) = guild_data.as_ref().and_then(|data| {
+                    data.find_menu_by_message(removed_reaction.message_id)
+                        .filter(|menu| menu.get_style() == MenuStyle::Reactions)
+                        .and_then(|menu| {
+                            menu.get_role(&removed_reaction.emoji)
+                                .map(|role_id| (data, menu, *role_id))
+                        })
+                }) {
+                    remove_reaction_role(&ctx, &self.db, data, menu, guild_id, user_id, role_id)
+                        .await;
                 }
             }
         }
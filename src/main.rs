@@ -1,17 +1,31 @@
+mod audit_log;
+mod cleanup;
 mod commands;
 mod database;
+mod emoji;
+mod expiry;
 mod guild_data;
 mod handler;
+mod permissions;
 mod role_management;
+mod role_menu;
+mod schedule;
+mod self_service;
+mod sync;
 mod util;
 
-use std::{env, path::Path};
+use std::env;
 
+use database::{Database, PickleStorage, Storage};
 use handler::Handler;
 use log::error;
-use pickledb::{PickleDb, PickleDbDumpPolicy};
 use serenity::prelude::{Client, GatewayIntents};
 
+const PICKLE_PATH: &str = "roly-poly-rolies.db";
+
+#[cfg(feature = "sqlite")]
+const SQLITE_PATH: &str = "roly-poly-rolies.sqlite";
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -19,15 +33,18 @@ async fn main() {
     let token = env::var("DISCORD_BOT_TOKEN")
         .expect("Expected DISCORD_BOT_TOKEN environment variable to be set");
 
-    let db_name = "roly-poly-rolies.db";
-    let db = if Path::new(db_name).exists() {
-        PickleDb::load_json(db_name, PickleDbDumpPolicy::AutoDump).expect("A valid database file")
-    } else {
-        PickleDb::new_json(db_name, PickleDbDumpPolicy::AutoDump)
-    };
+    let db = open_storage(
+        env::var("DATABASE_BACKEND")
+            .as_deref()
+            .unwrap_or("pickledb"),
+    );
 
-    let mut client = Client::builder(token, GatewayIntents::GUILD_MESSAGE_REACTIONS)
-        .event_handler(Handler::new(db))
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_EMOJIS_AND_STICKERS
+        | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS;
+    let mut client = Client::builder(token, intents)
+        .event_handler(Handler::new(Database::new(db)))
         .await
         .expect("Could not start bot");
 
@@ -35,3 +52,27 @@ async fn main() {
         error!("Bot client error: {:?}", why);
     }
 }
+
+/// Opens the SQLite database, first copying over the guilds from the PickleDB file when switching
+/// backends, so existing menus carry over
+#[cfg(feature = "sqlite")]
+fn open_sqlite_storage() -> database::SqliteStorage {
+    let storage = database::SqliteStorage::open(SQLITE_PATH);
+    storage
+        .import_pickle(PICKLE_PATH)
+        .expect("The PickleDB database to be imported into SQLite");
+    storage
+}
+
+fn open_storage(backend: &str) -> Box<dyn Storage> {
+    match backend {
+        "pickledb" => Box::new(PickleStorage::open(PICKLE_PATH)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Box::new(open_sqlite_storage()),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => {
+            panic!("DATABASE_BACKEND is sqlite but the bot was built without the sqlite feature")
+        }
+        other => panic!("Unknown DATABASE_BACKEND {other}, expected pickledb or sqlite"),
+    }
+}
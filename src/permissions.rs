@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use serenity::{
+    model::{
+        error::Error as ModelError,
+        guild::Role,
+        id::{ChannelId, GuildId, RoleId},
+        Permissions,
+    },
+    prelude::Context,
+};
+
+use crate::role_menu::MenuStyle;
+
+/// Why the bot would be unable to hand out a role
+pub enum RoleProblem {
+    Missing,
+    Everyone,
+    Managed,
+    CannotManageRoles,
+    AboveBot,
+}
+
+impl RoleProblem {
+    pub fn describe(&self, role: &str) -> String {
+        match self {
+            RoleProblem::Missing => format!("{role} no longer exists"),
+            RoleProblem::Everyone => {
+                "@everyone is held by every member and cannot be assigned".to_owned()
+            }
+            RoleProblem::Managed => format!(
+                "{role} is managed by an integration such as a bot or server boosts, so it cannot \
+                 be assigned by hand"
+            ),
+            RoleProblem::CannotManageRoles => {
+                format!("The bot cannot assign {role} without the Manage Roles permission")
+            }
+            RoleProblem::AboveBot => format!(
+                "{role} is not below the bot's highest role, so the bot cannot assign it. Move \
+                 the bot's role above it in the server settings."
+            ),
+        }
+    }
+}
+
+/// The guild's roles along with the position of the bot's highest role, for checking which roles
+/// the bot may assign
+pub struct RoleHierarchy {
+    guild_id: GuildId,
+    roles: HashMap<RoleId, Role>,
+    bot_position: u16,
+    can_manage_roles: bool,
+}
+
+impl RoleHierarchy {
+    pub async fn fetch(ctx: &Context, guild_id: GuildId) -> serenity::Result<Self> {
+        let bot_id = ctx.cache.current_user().id;
+        let roles = guild_id.roles(ctx).await?;
+        let bot = guild_id.member(ctx, bot_id).await?;
+        // Every member implicitly holds @everyone, whose ID is the guild's
+        let bot_roles: Vec<&Role> = bot
+            .roles
+            .iter()
+            .chain([&guild_id.everyone_role()])
+            .filter_map(|role_id| roles.get(role_id))
+            .collect();
+        let bot_position = bot_roles
+            .iter()
+            .map(|role| role.position)
+            .max()
+            .unwrap_or_default();
+        let can_manage_roles = bot_roles.iter().any(|role| {
+            role.permissions
+                .intersects(Permissions::MANAGE_ROLES | Permissions::ADMINISTRATOR)
+        });
+        Ok(Self {
+            guild_id,
+            roles,
+            bot_position,
+            can_manage_roles,
+        })
+    }
+
+    pub fn check(&self, role_id: u64) -> Option<RoleProblem> {
+        if role_id == self.guild_id.get() {
+            return Some(RoleProblem::Everyone);
+        }
+        match self.roles.get(&RoleId::new(role_id)) {
+            None => Some(RoleProblem::Missing),
+            Some(role) if role.managed => Some(RoleProblem::Managed),
+            Some(_) if !self.can_manage_roles => Some(RoleProblem::CannotManageRoles),
+            Some(role) if role.position >= self.bot_position => Some(RoleProblem::AboveBot),
+            Some(_) => None,
+        }
+    }
+}
+
+/// The permissions the bot needs in a channel to post a menu of the given style
+pub fn required_channel_permissions(style: MenuStyle) -> Permissions {
+    let permissions =
+        Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS;
+    match style {
+        // Discord only lets the bot react to messages whose history it can read
+        MenuStyle::Reactions => {
+            permissions | Permissions::ADD_REACTIONS | Permissions::READ_MESSAGE_HISTORY
+        }
+        MenuStyle::Buttons | MenuStyle::Select => permissions,
+    }
+}
+
+/// Finds which of the required permissions the bot lacks in a channel
+pub async fn find_missing_channel_permissions(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    required: Permissions,
+) -> serenity::Result<Permissions> {
+    let bot_id = ctx.cache.current_user().id;
+    let bot = guild_id.member(ctx, bot_id).await?;
+    let guild = ctx.cache.guild(guild_id).ok_or(ModelError::GuildNotFound)?;
+    let channel = guild
+        .channels
+        .get(&channel_id)
+        .ok_or(ModelError::ChannelNotFound)?;
+    Ok(required - guild.user_permissions_in(channel, &bot))
+}
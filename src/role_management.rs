@@ -1,26 +1,60 @@
-use std::{str::FromStr, sync::RwLock};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    str::FromStr,
+};
 
-use bimap::BiMap;
 use log::{error, warn};
-use pickledb::PickleDb;
 use serenity::{
-    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
-    futures::{stream::FuturesUnordered, StreamExt},
+    builder::{
+        CreateActionRow, CreateAutocompleteResponse, CreateButton, CreateInteractionResponse,
+        CreateInteractionResponseMessage, EditInteractionResponse,
+    },
     model::{
-        application::{CommandDataOption, CommandDataOptionValue, CommandInteraction},
+        application::{
+            ButtonStyle, CommandDataOption, CommandDataOptionValue, CommandInteraction,
+            ComponentInteraction,
+        },
         channel::ReactionType,
-        id::EmojiId,
-        misc::EmojiIdentifier,
+        id::{ChannelId, EmojiId, GuildId, RoleId, UserId},
+        Timestamp,
     },
     prelude::Context,
 };
 
 use crate::{
-    database::{get_guild_data, update_guild_data},
+    audit_log::AuditLog,
+    database::{Database, GuildTransaction},
+    emoji::{search_unicode_emoji, EmojiIndex},
+    expiry::describe_duration,
     guild_data::GuildData,
-    util::get_guild_id,
+    permissions::{find_missing_channel_permissions, required_channel_permissions, RoleHierarchy},
+    role_menu::{
+        MenuAppearance, MenuStyle, MenuWindow, RoleConflict, RoleMenu, APPEARANCE_MAX_LENGTH,
+        BUNDLE_MAX_ROLES,
+    },
+    self_service::{grant_bundle, revoke_roles},
+    sync::{get_members, get_reactors, sync_menu, SyncSummary},
+    util::{
+        get_boolean_option, get_channel_option, get_guild_id, get_integer_option, get_role_option,
+        get_string_option,
+    },
 };
 
+/// Prefix of the custom ID given to the button confirming that `enable` may overwrite part of a
+/// menu, followed by the role ID, the emoji and the menu name
+pub const REPLACE_ROLE_PREFIX: &str = "replace-role:";
+
+/// Discord shows at most 25 autocomplete suggestions
+const AUTOCOMPLETE_MAX_CHOICES: usize = 25;
+
+/// Shown when a guild's stored configuration cannot be read, as changing it would erase it
+const STORAGE_ERROR_CONTENT: &str = "Could not read this server's menus, so nothing was changed";
+
+/// Discord rejects message content longer than 2000 characters
+const MESSAGE_MAX_LENGTH: usize = 2000;
+
 pub async fn respond_to_command<S>(ctx: &Context, command: &CommandInteraction, content: S)
 where
     S: Into<String>,
@@ -42,48 +76,76 @@ where
 
 pub async fn enable_role(
     ctx: &Context,
-    db: &RwLock<PickleDb>,
+    db: &Database,
+    emoji_index: &EmojiIndex,
     command: &CommandInteraction,
     opt: &CommandDataOption,
 ) {
     if let CommandDataOptionValue::SubCommand(options) = &opt.value {
-        match &options[0..2] {
+        match &options[0..3] {
             [CommandDataOption {
                 name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
                 value: CommandDataOptionValue::Role(role_id),
                 ..
             }, CommandDataOption {
-                name: op2_name,
+                name: opt3_name,
                 value: CommandDataOptionValue::String(emoji_name),
                 ..
-            }] if opt1_name == "role" && op2_name == "emoji" => {
+            }] if opt1_name == "menu" && opt2_name == "role" && opt3_name == "emoji" => {
                 let guild_id = get_guild_id(command);
-                let guild_data = get_guild_data(db, guild_id);
-                let maybe_emoji = get_emoji(ctx, emoji_name).await;
-
-                if let Some(emoji) = maybe_emoji {
-                    if let Some(mut data) = guild_data {
-                        data.add_role(ctx, (*role_id).into(), emoji).await;
-                        update_guild_data(db, guild_id, &data);
-                    } else {
-                        let mut roles_to_emoji: BiMap<u64, ReactionType> = BiMap::new();
-                        roles_to_emoji.insert((*role_id).into(), emoji);
-
-                        update_guild_data(db, guild_id, &GuildData::new(roles_to_emoji));
-                    }
-
-                    respond_to_command(
-                        ctx,
-                        command,
-                        format!(
-                            "Enabled {} for self-service access",
-                            command.data.resolved.roles[&role_id].name
-                        ),
-                    )
-                    .await;
-                } else {
-                    respond_to_command(ctx, command, format!("Could not find emoji: {emoji_name}"))
+                if let Err(content) = check_roles(ctx, guild_id, [role_id.get()]).await {
+                    respond_to_command(ctx, command, content).await;
+                    return;
+                }
+                match emoji_index.resolve(ctx, guild_id, emoji_name).await {
+                    Ok(emoji) => {
+                        let result = add_menu_role(
+                            ctx,
+                            db,
+                            guild_id,
+                            menu_name,
+                            role_id.get(),
+                            &emoji,
+                            |conflicts| conflicts.is_empty(),
+                        )
                         .await;
+                        let content = match result {
+                            Ok(audit_log) => {
+                                audit_log
+                                    .role_enabled(ctx, command.user.id, menu_name, role_id.get())
+                                    .await;
+                                format!(
+                                    "Enabled {} for self-service access in the {menu_name} menu",
+                                    command.data.resolved.roles[role_id].name
+                                )
+                            }
+                            Err(EnableError::Conflicts(conflicts)) => {
+                                let prompt = confirm_conflicts(
+                                    menu_name,
+                                    role_id.get(),
+                                    &emoji,
+                                    &conflicts,
+                                );
+                                if let Err(e) = command
+                                    .create_response(
+                                        ctx,
+                                        CreateInteractionResponse::Message(prompt.ephemeral(true)),
+                                    )
+                                    .await
+                                {
+                                    error!("Could not respond to command: {:?}", e);
+                                }
+                                return;
+                            }
+                            Err(EnableError::Storage) => STORAGE_ERROR_CONTENT.to_owned(),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    Err(content) => respond_to_command(ctx, command, content).await,
                 }
             }
             _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
@@ -91,36 +153,304 @@ pub async fn enable_role(
     }
 }
 
+/// Why a role could not be enabled
+enum EnableError {
+    /// Enabling the role would overwrite part of the menu the admin has not agreed to
+    Conflicts(Vec<RoleConflict>),
+    Storage,
+}
+
+/// Builds a prompt asking the admin to confirm enabling a role that would overwrite part of the
+/// menu
+fn confirm_conflicts(
+    menu_name: &str,
+    role_id: u64,
+    emoji: &ReactionType,
+    conflicts: &[RoleConflict],
+) -> CreateInteractionResponseMessage {
+    let content = describe_conflicts(menu_name, role_id, emoji, conflicts);
+
+    // Custom emoji are looked up again by ID, as their full form may not fit in a custom ID
+    let emoji_key = match emoji {
+        ReactionType::Custom { id, .. } => id.to_string(),
+        other => other.to_string(),
+    };
+    let button = CreateButton::new(format!(
+        "{REPLACE_ROLE_PREFIX}{role_id}:{emoji_key}:{menu_name}"
+    ))
+    .style(ButtonStyle::Danger)
+    .label("Replace");
+
+    CreateInteractionResponseMessage::new()
+        .content(content)
+        .components(vec![CreateActionRow::Buttons(vec![button])])
+}
+
+/// Lists what enabling the role would overwrite, which is also how a confirmation is matched
+/// against the menu once the admin answers it
+fn describe_conflicts(
+    menu_name: &str,
+    role_id: u64,
+    emoji: &ReactionType,
+    conflicts: &[RoleConflict],
+) -> String {
+    let mut content =
+        format!("Enabling <@&{role_id}> with {emoji} in the {menu_name} menu would:\n");
+    for conflict in conflicts {
+        writeln!(content, "- {}", conflict.describe(role_id, emoji))
+            .expect("String concatenation success");
+    }
+    content.push_str("Dismiss this message to leave the menu as it is.");
+    content
+}
+
+/// Enables a role once the admin has confirmed it may overwrite part of the menu
+pub async fn confirm_enable_role(
+    ctx: &Context,
+    db: &Database,
+    emoji_index: &EmojiIndex,
+    component: &ComponentInteraction,
+) {
+    let parsed = component
+        .data
+        .custom_id
+        .strip_prefix(REPLACE_ROLE_PREFIX)
+        .and_then(|rest| {
+            let mut parts = rest.splitn(3, ':');
+            let role_id = parts.next()?.parse::<u64>().ok()?;
+            Some((role_id, parts.next()?, parts.next()?))
+        });
+
+    match (component.guild_id, parsed) {
+        (Some(guild_id), Some((role_id, emoji_key, menu_name))) => {
+            let emoji = match emoji_key.parse::<u64>() {
+                Ok(emoji_id) => emoji_index.get_reaction(EmojiId::new(emoji_id)),
+                Err(_) => Some(ReactionType::Unicode(emoji_key.to_owned())),
+            };
+            // Answers other than a new prompt take away the Replace button
+            let answer = |content: String| {
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(Vec::new())
+            };
+            let response = match emoji {
+                // The menu may have changed since the admin was asked, in which case they are
+                // asked again about what enabling the role would now overwrite
+                Some(emoji) => match add_menu_role(
+                    ctx,
+                    db,
+                    guild_id,
+                    menu_name,
+                    role_id,
+                    &emoji,
+                    |conflicts| {
+                        conflicts.is_empty()
+                            || describe_conflicts(menu_name, role_id, &emoji, conflicts)
+                                == component.message.content
+                    },
+                )
+                .await
+                {
+                    Ok(audit_log) => {
+                        audit_log
+                            .role_enabled(ctx, component.user.id, menu_name, role_id)
+                            .await;
+                        answer(format!(
+                            "Enabled <@&{role_id}> for self-service access in the {menu_name} menu"
+                        ))
+                    }
+                    Err(EnableError::Conflicts(conflicts)) => {
+                        confirm_conflicts(menu_name, role_id, &emoji, &conflicts)
+                    }
+                    Err(EnableError::Storage) => answer(STORAGE_ERROR_CONTENT.to_owned()),
+                },
+                None => answer("That emoji no longer exists".to_owned()),
+            };
+            if let Err(e) = component
+                .create_response(ctx, CreateInteractionResponse::UpdateMessage(response))
+                .await
+            {
+                error!("Could not respond to component interaction: {:?}", e);
+            }
+        }
+        _ => warn!("A component was used with an unexpected custom ID"),
+    }
+}
+
+/// Enables the role with the emoji, as long as the admin accepts what it would overwrite, and
+/// returns the guild's audit log to record it in. The overwrites are checked within the
+/// transaction, so a concurrent change cannot slip past them.
+async fn add_menu_role(
+    ctx: &Context,
+    db: &Database,
+    guild_id: GuildId,
+    menu_name: &str,
+    role_id: u64,
+    emoji: &ReactionType,
+    accept: impl FnOnce(&[RoleConflict]) -> bool,
+) -> Result<AuditLog, EnableError> {
+    let mut data = db
+        .transaction(guild_id)
+        .await
+        .map_err(|_| EnableError::Storage)?;
+    let menu = data.get_or_create_menu(menu_name);
+    let conflicts = menu.find_conflicts(role_id, emoji);
+    if !accept(&conflicts) {
+        return Err(EnableError::Conflicts(conflicts));
+    }
+    menu.add_role(ctx, guild_id, role_id, emoji.clone()).await;
+    let audit_log = data.get_audit_log();
+    data.commit();
+    Ok(audit_log)
+}
+
+/// Starts a change to the guild's configuration, telling the admin if it could not be read
+async fn begin_change<'a>(
+    ctx: &Context,
+    db: &'a Database,
+    command: &CommandInteraction,
+    guild_id: GuildId,
+) -> Option<GuildTransaction<'a>> {
+    match db.transaction(guild_id).await {
+        Ok(data) => Some(data),
+        Err(_) => {
+            respond_to_command(ctx, command, STORAGE_ERROR_CONTENT).await;
+            None
+        }
+    }
+}
+
+/// Suggests emoji for `enable` that are not yet used by the chosen menu, or by any menu if none
+/// has been chosen yet
+pub async fn suggest_emoji(
+    ctx: &Context,
+    db: &Database,
+    emoji_index: &EmojiIndex,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    let (CommandDataOptionValue::SubCommand(options), Some(focused)) =
+        (&opt.value, command.data.autocomplete())
+    else {
+        return;
+    };
+    if focused.name != "emoji" {
+        return;
+    }
+
+    let guild_id = get_guild_id(command);
+    let menu_name = get_string_option(options, "menu");
+    let used: Vec<ReactionType> = db
+        .get_guild_data(guild_id)
+        .map(|data| {
+            data.get_menus()
+                .filter(|(name, _)| menu_name.is_none_or(|menu_name| menu_name == *name))
+                .flat_map(|(_, menu)| menu.get_emojis().cloned())
+                .collect()
+        })
+        .unwrap_or_default();
+    // Custom emoji keep their ID when renamed, so only the ID identifies them
+    let is_used = |emoji: &ReactionType| {
+        used.iter().any(|used| match (used, emoji) {
+            (ReactionType::Custom { id: used_id, .. }, ReactionType::Custom { id, .. }) => {
+                used_id == id
+            }
+            _ => used == emoji,
+        })
+    };
+
+    let query = focused.value.trim().trim_matches(':').to_lowercase();
+    let custom = emoji_index
+        .get_guild_emoji(guild_id)
+        .into_iter()
+        .filter(|emoji| emoji.name.to_lowercase().contains(&query))
+        .map(|emoji| (format!(":{}:", emoji.name), ReactionType::from(emoji)));
+    let unicode = search_unicode_emoji(&query).map(|emoji| {
+        (
+            format!(
+                "{} :{}:",
+                emoji.as_str(),
+                emoji.shortcode().unwrap_or_default()
+            ),
+            ReactionType::Unicode(emoji.as_str().to_owned()),
+        )
+    });
+
+    let mut response = CreateAutocompleteResponse::new();
+    for (name, emoji) in custom
+        .chain(unicode)
+        .filter(|(_, emoji)| !is_used(emoji))
+        .take(AUTOCOMPLETE_MAX_CHOICES)
+    {
+        response = response.add_string_choice(name, emoji.to_string());
+    }
+    if let Err(e) = command
+        .create_response(ctx, CreateInteractionResponse::Autocomplete(response))
+        .await
+    {
+        error!("Could not suggest emoji: {:?}", e);
+    }
+}
+
 pub async fn disable_role(
     ctx: &Context,
-    db: &RwLock<PickleDb>,
+    db: &Database,
     command: &CommandInteraction,
     opt: &CommandDataOption,
 ) {
     if let CommandDataOptionValue::SubCommand(options) = &opt.value {
-        match options.first() {
-            Some(CommandDataOption {
-                name,
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
                 value: CommandDataOptionValue::Role(role_id),
                 ..
-            }) if name == "role" => {
+            }] if opt1_name == "menu" && opt2_name == "role" => {
                 let guild_id = get_guild_id(command);
-                let guild_data = get_guild_data(db, guild_id);
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
 
-                if let Some(mut data) = guild_data {
-                    data.remove_role(ctx, (*role_id).into()).await;
-                    update_guild_data(db, guild_id, &data);
-                }
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) if menu.has_role(role_id.get()) => {
+                        menu.remove_role(ctx, guild_id, (*role_id).into()).await;
+                        let audit_log = data.get_audit_log();
+                        data.commit();
+                        audit_log
+                            .role_disabled(ctx, command.user.id, menu_name, role_id.get())
+                            .await;
 
-                respond_to_command(
-                    ctx,
-                    command,
-                    format!(
-                        "Disabled {} for self-service access",
-                        command.data.resolved.roles[&role_id].name
-                    ),
-                )
-                .await;
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!(
+                                "Disabled {role_name} for self-service access in the {menu_name} \
+                                 menu"
+                            ),
+                        )
+                        .await;
+                    }
+                    Some(_) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
             }
             _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
         }
@@ -129,28 +459,41 @@ pub async fn disable_role(
 
 pub async fn create_message(
     ctx: &Context,
-    db: &RwLock<PickleDb>,
+    db: &Database,
     command: &CommandInteraction,
     opt: &CommandDataOption,
 ) {
     if let CommandDataOptionValue::SubCommand(options) = &opt.value {
-        match options.first() {
-            Some(CommandDataOption {
-                name,
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
                 value: CommandDataOptionValue::Channel(channel_id),
                 ..
-            }) if name == "channel" => {
+            }] if opt1_name == "menu" && opt2_name == "channel" => {
                 let guild_id = get_guild_id(command);
-                let guild_data = get_guild_data(db, guild_id);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+                let audit_log = data.get_audit_log();
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) => {
+                        if let Err(content) = check_can_post(ctx, guild_id, menu, *channel_id).await
+                        {
+                            respond_to_command(ctx, command, content).await;
+                            return;
+                        }
 
-                match guild_data {
-                    Some(mut data) => {
                         respond_to_command(
                             ctx,
                             command,
                             format!(
-                                "Sending a message to #{} if one does not already exist",
-                                command.data.resolved.channels[&channel_id]
+                                "Sending the {menu_name} menu to #{} if it does not already exist",
+                                command.data.resolved.channels[channel_id]
                                     .name
                                     .as_ref()
                                     .expect("Channels should be named")
@@ -158,10 +501,22 @@ pub async fn create_message(
                         )
                         .await;
 
-                        update_guild_data(db, guild_id, data.send_message(ctx, *channel_id).await);
+                        let had_message = menu.get_message_id().is_some();
+                        menu.send_message(ctx, guild_id, *channel_id).await;
+                        if !had_message && menu.get_message_id().is_some() {
+                            audit_log
+                                .message_posted(ctx, command.user.id, menu_name, menu, guild_id)
+                                .await;
+                        }
+                        data.commit();
                     }
                     None => {
-                        respond_to_command(ctx, command, "You have not configured any roles").await;
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
                     }
                 }
             }
@@ -170,30 +525,1437 @@ pub async fn create_message(
     }
 }
 
-async fn get_emoji(ctx: &Context, emoji_name: &str) -> Option<ReactionType> {
-    let all_emoji: Vec<EmojiId> = ctx
-        .cache
-        .guilds()
-        .iter()
-        .map(|guild| guild.emojis(&ctx))
-        .collect::<FuturesUnordered<_>>()
-        .collect::<Vec<_>>()
+pub async fn move_menu(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Channel(channel_id),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "channel" => {
+                let migrate = get_boolean_option(options, "migrate-roles").unwrap_or(true);
+                relocate_menu(ctx, db, command, menu_name, Some(*channel_id), migrate).await;
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn repost_menu(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match options.first() {
+            Some(CommandDataOption {
+                name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }) if name == "menu" => {
+                let migrate = get_boolean_option(options, "migrate-roles").unwrap_or(true);
+                relocate_menu(ctx, db, command, menu_name, None, migrate).await;
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+/// Deletes a menu's message and posts it again, in the given channel or else where it was.
+///
+/// Members cannot carry their reactions over to the new message, so those who reacted either keep
+/// their roles without a reaction or lose them so they can pick again.
+async fn relocate_menu(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    menu_name: &str,
+    channel_id: Option<ChannelId>,
+    migrate: bool,
+) {
+    let guild_id = get_guild_id(command);
+    let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+        return;
+    };
+    let audit_log = data.get_audit_log();
+    let shared = data.get_roles_from_other_menus(menu_name);
+    let Some(menu) = data.get_menu_mut(menu_name) else {
+        respond_to_command(
+            ctx,
+            command,
+            format!("You have not configured any roles for the {menu_name} menu"),
+        )
+        .await;
+        return;
+    };
+    let Some(channel_id) = channel_id.or(menu.get_channel_id()) else {
+        respond_to_command(
+            ctx,
+            command,
+            format!(
+                "The {menu_name} menu has not been posted yet. Post it with \
+                 /role self-service message."
+            ),
+        )
+        .await;
+        return;
+    };
+    if let Err(content) = check_can_post(ctx, guild_id, menu, channel_id).await {
+        respond_to_command(ctx, command, content).await;
+        return;
+    }
+
+    // Paging through reactions and updating members' roles can outlast the initial response
+    // deadline
+    if let Err(e) = command.defer_ephemeral(ctx).await {
+        error!("Could not respond to command: {:?}", e);
+        return;
+    }
+
+    let reactors = match menu.get_style() {
+        MenuStyle::Reactions => get_reactors(ctx, menu).await,
+        MenuStyle::Buttons | MenuStyle::Select => Ok(HashMap::new()),
+    };
+    let content = match reactors {
+        Ok(reactors) => match menu.delete_message(ctx).await {
+            Ok(()) => {
+                let mut holders = menu.get_carried_over().clone();
+                for (role_id, users) in reactors {
+                    holders
+                        .entry(role_id)
+                        .or_default()
+                        .extend(users.into_keys().map(|user_id| user_id.get()));
+                }
+                let member_count =
+                    carry_over_roles(ctx, guild_id, menu, holders, &shared, migrate).await;
+
+                menu.send_message(ctx, guild_id, channel_id).await;
+                let mut content = match menu.get_message_id() {
+                    Some(_) => {
+                        audit_log
+                            .message_posted(ctx, command.user.id, menu_name, menu, guild_id)
+                            .await;
+                        format!("Posted the {menu_name} menu in <#{channel_id}>")
+                    }
+                    None => format!("Could not post the {menu_name} menu in <#{channel_id}>"),
+                };
+                match (member_count, migrate) {
+                    (0, _) => {}
+                    (count, true) => write!(content, ". {count} members kept their roles")
+                        .expect("String concatenation success"),
+                    (count, false) => write!(
+                        content,
+                        ". Removed the menu's roles from {count} members so they can pick again"
+                    )
+                    .expect("String concatenation success"),
+                }
+                data.commit();
+                content
+            }
+            Err(e) => {
+                error!(
+                    "Could not delete the message for the {} menu in guild {:?}: {:?}",
+                    menu_name, guild_id, e
+                );
+                format!("Could not delete the old message for the {menu_name} menu")
+            }
+        },
+        Err(e) => {
+            error!(
+                "Could not read reactions for the {} menu in guild {:?}: {:?}",
+                menu_name, guild_id, e
+            );
+            format!("Could not read the reactions on the old message for the {menu_name} menu")
+        }
+    };
+
+    if let Err(e) = command
+        .edit_response(ctx, EditInteractionResponse::new().content(content))
         .await
-        .into_iter()
-        .filter_map(|result| {
-            result
-                .ok()
-                .map(|emojis| emojis.iter().map(|emoji| emoji.id).collect::<Vec<_>>())
-        })
-        .flatten()
-        .collect();
-
-    if emoji_name.starts_with('<') {
-        EmojiIdentifier::from_str(emoji_name)
-            .ok()
-            .filter(|identifier| all_emoji.contains(&identifier.id))
-            .map(Into::into)
-    } else {
-        emoji_name.chars().next().map(Into::into)
+    {
+        error!("Could not respond to command: {:?}", e);
+    }
+}
+
+/// Lets the members who held a moved menu's roles keep them, or takes the roles away, returning
+/// how many members were affected
+async fn carry_over_roles(
+    ctx: &Context,
+    guild_id: GuildId,
+    menu: &mut RoleMenu,
+    holders: BTreeMap<u64, BTreeSet<u64>>,
+    shared: &BTreeSet<u64>,
+    migrate: bool,
+) -> usize {
+    let mut members = BTreeSet::new();
+    for (role_id, users) in &holders {
+        for user_id in users {
+            let user = UserId::new(*user_id);
+            let reason = Some("Menu moved");
+            let result = if migrate {
+                // Members who keep their roles already hold the bundle, so nothing is taken back
+                // if part of it cannot be given
+                let held: Vec<RoleId> = menu
+                    .get_bundle(*role_id)
+                    .into_iter()
+                    .map(RoleId::new)
+                    .collect();
+                grant_bundle(ctx, menu, guild_id, user, &held, *role_id, reason).await
+            } else {
+                // The menu's roles are all taken away together, while members may have picked
+                // the roles the guild's other menus hand out there
+                let roles: Vec<u64> = menu
+                    .get_bundle(*role_id)
+                    .into_iter()
+                    .filter(|role| !shared.contains(role))
+                    .collect();
+                revoke_roles(ctx, guild_id, user, &roles, reason).await
+            };
+            match result {
+                Ok(()) => {
+                    members.insert(*user_id);
+                }
+                Err(e) => error!("Could not update roles for user {:?}: {:?}", user, e),
+            }
+        }
+    }
+    menu.set_carried_over(if migrate { holders } else { BTreeMap::new() });
+    members.len()
+}
+
+pub async fn set_style(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::String(style_name),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "style" => {
+                let guild_id = get_guild_id(command);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match (data.get_menu_mut(menu_name), MenuStyle::from_str(style_name)) {
+                    (Some(menu), Ok(style)) => {
+                        menu.set_choice_limits(
+                            get_integer_option(options, "min-choices")
+                                .and_then(|min| u8::try_from(min).ok()),
+                            get_integer_option(options, "max-choices")
+                                .and_then(|max| u8::try_from(max).ok()),
+                        );
+                        menu.set_style(ctx, guild_id, style).await;
+                        data.commit();
+
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("The {menu_name} menu now uses {style_name}"),
+                        )
+                        .await;
+                    }
+                    (None, _) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    (_, Err(())) => warn!("A command was invoked with an unknown menu style"),
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn describe_role(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Role(role_id),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "role" => {
+                let guild_id = get_guild_id(command);
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let description = get_string_option(options, "description");
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) if menu.has_role(role_id.get()) => {
+                        menu.set_description(
+                            ctx,
+                            guild_id,
+                            role_id.get(),
+                            description.map(str::to_owned),
+                        )
+                        .await;
+                        data.commit();
+
+                        let content = match description {
+                            Some(_) => format!("Described {role_name} in the {menu_name} menu"),
+                            None => format!(
+                                "Removed the description of {role_name} in the {menu_name} menu"
+                            ),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    Some(_) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn reorder_roles(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match options.first() {
+            Some(CommandDataOption {
+                name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }) if name == "menu" => {
+                let guild_id = get_guild_id(command);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+                let Some(menu) = data.get_menu_mut(menu_name) else {
+                    respond_to_command(
+                        ctx,
+                        command,
+                        format!("You have not configured any roles for the {menu_name} menu"),
+                    )
+                    .await;
+                    return;
+                };
+
+                let mut content = match (
+                    get_string_option(options, "sort"),
+                    get_role_option(options, "role"),
+                    get_integer_option(options, "position"),
+                ) {
+                    (Some(sort), _, _) => {
+                        let roles = match guild_id.roles(ctx).await {
+                            Ok(roles) => roles,
+                            Err(e) => {
+                                error!("Could not fetch roles for guild {:?}: {:?}", guild_id, e);
+                                respond_to_command(ctx, command, "Could not look up the roles")
+                                    .await;
+                                return;
+                            }
+                        };
+                        let mut order = menu.get_role_ids();
+                        let role = |role_id: &u64| roles.get(&RoleId::new(*role_id));
+                        if sort == "name" {
+                            order.sort_by_cached_key(|role_id| {
+                                role(role_id).map(|role| role.name.to_lowercase())
+                            });
+                        } else {
+                            // Discord lists the highest roles first
+                            order.sort_by_key(|role_id| {
+                                Reverse(role(role_id).map(|role| role.position))
+                            });
+                        }
+                        menu.set_order(ctx, guild_id, order).await;
+                        let sort = if sort == "name" { "name" } else { "role position" };
+                        format!("Sorted the {menu_name} menu by {sort}")
+                    }
+                    (None, Some(role_id), Some(position)) if menu.has_role(role_id.get()) => {
+                        let index = usize::try_from(position - 1).unwrap_or_default();
+                        menu.move_role(ctx, guild_id, role_id.get(), index).await;
+                        format!(
+                            "Moved <@&{role_id}> to position {position} in the {menu_name} menu"
+                        )
+                    }
+                    (None, Some(role_id), Some(_)) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("<@&{role_id}> is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                        return;
+                    }
+                    _ => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            "Give a role and the position to move it to, or a way to sort the menu",
+                        )
+                        .await;
+                        return;
+                    }
+                };
+
+                // Discord shows reactions in the order they were first added, and removing them
+                // would take away members' picks
+                if menu.get_style() == MenuStyle::Reactions && menu.get_message_id().is_some() {
+                    content.push_str(
+                        ". Repost the menu with /role self-service repost to put its reactions \
+                         in the new order",
+                    );
+                }
+                data.commit();
+                respond_to_command(ctx, command, content).await;
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_appearance(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match options.first() {
+            Some(CommandDataOption {
+                name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }) if name == "menu" => {
+                let guild_id = get_guild_id(command);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) => {
+                        let reset = get_boolean_option(options, "reset").unwrap_or(false);
+                        let mut appearance = if reset {
+                            MenuAppearance::default()
+                        } else {
+                            menu.get_appearance().clone()
+                        };
+                        if let Err(content) = update_appearance(&mut appearance, options) {
+                            respond_to_command(ctx, command, content).await;
+                            return;
+                        }
+                        menu.set_appearance(ctx, guild_id, appearance).await;
+                        data.commit();
+
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("Updated the appearance of the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+/// Applies the appearance options that were given, explaining the first invalid one
+fn update_appearance(
+    appearance: &mut MenuAppearance,
+    options: &[CommandDataOption],
+) -> Result<(), String> {
+    // Slash command arguments cannot contain line breaks, so they are written as \n instead
+    let text = |name| get_string_option(options, name).map(|text| text.replace("\\n", "\n"));
+    if let Some(title) = text("title") {
+        appearance.title = Some(title);
+    }
+    if let Some(description) = text("description") {
+        appearance.description = Some(description);
+    }
+    if let Some(footer) = text("footer") {
+        appearance.footer = Some(footer);
+    }
+    if let Some(color) = get_string_option(options, "color") {
+        let hex = color.trim_start_matches('#');
+        match u32::from_str_radix(hex, 16) {
+            Ok(value) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                appearance.color = Some(value);
+            }
+            _ => {
+                return Err(format!(
+                    "{color} is not a color, give a hex code such as #5865F2"
+                ))
+            }
+        }
+    }
+    for (name, field) in [
+        ("thumbnail", &mut appearance.thumbnail),
+        ("image", &mut appearance.image),
+    ] {
+        if let Some(url) = get_string_option(options, name) {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(format!("The {name} must be a link to an image, not {url}"));
+            }
+            *field = Some(url.to_owned());
+        }
+    }
+
+    let length = [
+        &appearance.title,
+        &appearance.description,
+        &appearance.footer,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|text| text.chars().count())
+    .sum::<usize>();
+    if length > APPEARANCE_MAX_LENGTH {
+        return Err(format!(
+            "The title, description and footer can be at most {APPEARANCE_MAX_LENGTH} \
+             characters together, leaving room for the menu's roles"
+        ));
+    }
+    Ok(())
+}
+
+pub async fn show_warnings(ctx: &Context, db: &Database, command: &CommandInteraction) {
+    let guild_id = get_guild_id(command);
+    let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+        return;
+    };
+    let count = data.get_warning_count();
+    if count == 0 {
+        respond_to_command(ctx, command, "There are no warnings").await;
+        return;
+    }
+
+    // Warnings that do not fit in one reply are kept until the next time, so leave room to say so
+    let remaining_note =
+        |remaining: usize| format!("…and {remaining} more, use this command again to see them");
+    let room = MESSAGE_MAX_LENGTH - remaining_note(count).chars().count();
+    let mut content = String::new();
+    let mut shown = 0;
+    for warning in data.get_warnings() {
+        let line = format!(
+            "<t:{}:R> {}\n",
+            warning.get_time().unix_timestamp(),
+            warning.get_message()
+        );
+        if content.chars().count() + line.chars().count() > room {
+            break;
+        }
+        content.push_str(&line);
+        shown += 1;
+    }
+    if shown < count {
+        content.push_str(&remaining_note(count - shown));
+    }
+
+    // Warnings are only dismissed once the admin has actually seen them
+    match command
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .content(content),
+            ),
+        )
+        .await
+    {
+        Ok(()) => {
+            data.dismiss_warnings(shown);
+            data.commit();
+        }
+        Err(e) => error!("Could not respond to command: {:?}", e),
+    }
+}
+
+pub async fn list_menus(
+    ctx: &Context,
+    db: &Database,
+    emoji_index: &EmojiIndex,
+    command: &CommandInteraction,
+) {
+    let guild_id = get_guild_id(command);
+    let data = db.get_guild_data(guild_id).unwrap_or_default();
+    if data.get_menus().next().is_none() {
+        respond_to_command(ctx, command, "You have not configured any menus").await;
+        return;
+    }
+
+    let hierarchy = match RoleHierarchy::fetch(ctx, guild_id).await {
+        Ok(hierarchy) => hierarchy,
+        Err(e) => {
+            error!("Could not fetch roles for guild {:?}: {:?}", guild_id, e);
+            respond_to_command(ctx, command, "Could not look up the server's roles").await;
+            return;
+        }
+    };
+
+    let mut content = String::new();
+    let mut problems = Vec::new();
+    for (name, menu) in data.get_menus() {
+        match (menu.get_channel_id(), menu.get_message_id()) {
+            (Some(channel_id), Some(message_id)) => writeln!(
+                content,
+                "**{name}**: {}",
+                message_id.link(channel_id, Some(guild_id))
+            ),
+            _ => writeln!(content, "**{name}**: not posted yet"),
+        }
+        .expect("String concatenation success");
+
+        let missing_emoji =
+            menu.find_roles_with_missing_emoji(|emoji_id| emoji_index.contains(emoji_id));
+        for role_id in menu.get_role_ids() {
+            let emoji = menu
+                .get_emoji(role_id)
+                .expect("Every enabled role has an emoji");
+            let bundle = menu
+                .get_bundle(role_id)
+                .iter()
+                .map(|role| format!("<@&{role}>"))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            match menu.get_duration(role_id) {
+                Some(seconds) => writeln!(
+                    content,
+                    "{emoji} {bundle} (expires after {})",
+                    describe_duration(seconds)
+                ),
+                None => writeln!(content, "{emoji} {bundle}"),
+            }
+            .expect("String concatenation success");
+
+            let role = format!("<@&{role_id}>");
+            if missing_emoji.contains(&role_id) {
+                problems.push(format!(
+                    "{name} menu: the emoji for {role} no longer exists"
+                ));
+            }
+            for bundled in menu.get_bundle(role_id) {
+                if let Some(problem) = hierarchy.check(bundled) {
+                    let bundled = format!("<@&{bundled}>");
+                    problems.push(format!("{name} menu: {}", problem.describe(&bundled)));
+                }
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        content.push_str("\n**Problems**\n");
+        for problem in problems {
+            writeln!(content, "- {problem}").expect("String concatenation success");
+        }
+    }
+    let warning_count = data.get_warning_count();
+    if warning_count > 0 {
+        write!(
+            content,
+            "\nThere are {warning_count} unread warnings, see /role self-service warnings"
+        )
+        .expect("String concatenation success");
+    }
+    respond_to_command(ctx, command, truncate_message(content)).await;
+}
+
+/// Cuts a reply down to Discord's message length limit at a line break, noting that it was cut
+fn truncate_message(mut content: String) -> String {
+    const TRUNCATED: &str = "\n…and more";
+    if content.chars().count() <= MESSAGE_MAX_LENGTH {
+        return content;
+    }
+
+    let limit = MESSAGE_MAX_LENGTH - TRUNCATED.chars().count();
+    let cut = content
+        .char_indices()
+        .nth(limit)
+        .map(|(index, _)| index)
+        .unwrap_or(content.len());
+    let cut = content[..cut].rfind('\n').unwrap_or(cut);
+    content.truncate(cut);
+    content.push_str(TRUNCATED);
+    content
+}
+
+pub async fn sync_reactions(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        let guild_id = get_guild_id(command);
+        let menu_name = get_string_option(options, "menu");
+        let data = db.get_guild_data(guild_id).unwrap_or_default();
+        let menus: Vec<_> = data
+            .get_menus()
+            .filter(|(name, _)| menu_name.is_none_or(|menu_name| menu_name == *name))
+            .collect();
+
+        if menus.is_empty() {
+            respond_to_command(ctx, command, "You have not configured any matching menus").await;
+            return;
+        }
+
+        // Paging through every reaction and member can outlast the initial response deadline
+        if let Err(e) = command.defer_ephemeral(ctx).await {
+            error!("Could not respond to command: {:?}", e);
+            return;
+        }
+
+        let mut summary = SyncSummary::default();
+        let mut failed = Vec::new();
+        for (name, _) in menus {
+            match sync_menu(ctx, db, guild_id, &data, name, true).await {
+                Ok(menu_summary) => {
+                    data.get_audit_log()
+                        .menu_synced(ctx, name, &menu_summary)
+                        .await;
+                    summary += menu_summary;
+                }
+                Err(e) => {
+                    error!(
+                        "Could not sync the {} menu for guild {:?}: {:?}",
+                        name, guild_id, e
+                    );
+                    failed.push(name.as_str());
+                }
+            }
+        }
+
+        let mut content = format!("Synced reactions: {summary}");
+        if !failed.is_empty() {
+            write!(content, "\nCould not sync the {} menus", failed.join(", "))
+                .expect("String concatenation success");
+        }
+        if let Err(e) = command
+            .edit_response(ctx, EditInteractionResponse::new().content(content))
+            .await
+        {
+            error!("Could not respond to command: {:?}", e);
+        }
+    }
+}
+
+pub async fn set_limit(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match options.first() {
+            Some(CommandDataOption {
+                name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }) if name == "menu" => {
+                let guild_id = get_guild_id(command);
+                let max = get_integer_option(options, "max").and_then(|max| u8::try_from(max).ok());
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) => {
+                        menu.set_max_choices(ctx, guild_id, max).await;
+                        data.commit();
+
+                        let content = match max {
+                            Some(max) => format!(
+                                "Members may now hold at most {max} roles from the {menu_name} menu"
+                            ),
+                            None => format!(
+                                "Members may now hold any number of roles from the {menu_name} menu"
+                            ),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_requirement(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..4] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Role(role_id),
+                ..
+            }, CommandDataOption {
+                name: opt3_name,
+                value: CommandDataOptionValue::Role(other_role_id),
+                ..
+            }, CommandDataOption {
+                name: opt4_name,
+                value: CommandDataOptionValue::String(kind),
+                ..
+            }] if opt1_name == "menu"
+                && opt2_name == "role"
+                && opt3_name == "other-role"
+                && opt4_name == "kind" =>
+            {
+                let guild_id = get_guild_id(command);
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let other_role_name = &command.data.resolved.roles[other_role_id].name;
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) if menu.has_role((*role_id).into()) => {
+                        menu.set_requirement(
+                            (*role_id).into(),
+                            (*other_role_id).into(),
+                            kind == "required",
+                            kind == "blocked",
+                        );
+                        data.commit();
+
+                        let content = match kind.as_str() {
+                            "required" => {
+                                format!("Members now need {other_role_name} to pick {role_name}")
+                            }
+                            "blocked" => format!(
+                                "Members with {other_role_name} can no longer pick {role_name}"
+                            ),
+                            _ => format!("{other_role_name} no longer affects picking {role_name}"),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    _ => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_group(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Role(role_id),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "role" => {
+                let guild_id = get_guild_id(command);
+                let group = get_string_option(options, "group");
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) if menu.has_role((*role_id).into()) => {
+                        menu.set_role_group((*role_id).into(), group);
+                        data.commit();
+
+                        let content = match group {
+                            Some(group) => format!("Moved {role_name} into the {group} group"),
+                            None => format!("Removed {role_name} from its group"),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    _ => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_exclusive(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Boolean(exclusive),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "exclusive" => {
+                let guild_id = get_guild_id(command);
+                let group = get_string_option(options, "group");
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) => {
+                        menu.set_exclusive(ctx, guild_id, group, *exclusive).await;
+                        data.commit();
+
+                        let target = match group {
+                            Some(group) => format!("The {group} group in the {menu_name} menu"),
+                            None => format!("The {menu_name} menu"),
+                        };
+                        let content = if *exclusive {
+                            format!("{target} now allows holding only one of its roles")
+                        } else {
+                            format!("{target} now allows holding any of its roles")
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_duration(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..2] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Role(role_id),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "role" => {
+                let guild_id = get_guild_id(command);
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let minutes = get_integer_option(options, "hours").unwrap_or_default() * 60
+                    + get_integer_option(options, "minutes").unwrap_or_default();
+                let seconds = u64::try_from(minutes * 60).ok().filter(|seconds| *seconds > 0);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+
+                match data.get_menu_mut(menu_name) {
+                    Some(menu) if menu.has_role(role_id.get()) => {
+                        menu.set_duration(role_id.get(), seconds);
+                        data.commit();
+
+                        // Members who already hold the role keep it, since it was not temporary
+                        // when they picked it
+                        let content = match seconds {
+                            Some(seconds) => format!(
+                                "Members who pick {role_name} from the {menu_name} menu will lose \
+                                 it again after {}",
+                                describe_duration(seconds)
+                            ),
+                            None => format!(
+                                "Members who pick {role_name} from the {menu_name} menu will keep \
+                                 it"
+                            ),
+                        };
+                        respond_to_command(ctx, command, content).await;
+                    }
+                    Some(_) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+pub async fn set_bundle(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match &options[0..3] {
+            [CommandDataOption {
+                name: opt1_name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }, CommandDataOption {
+                name: opt2_name,
+                value: CommandDataOptionValue::Role(role_id),
+                ..
+            }, CommandDataOption {
+                name: opt3_name,
+                value: CommandDataOptionValue::Role(other_role_id),
+                ..
+            }] if opt1_name == "menu" && opt2_name == "role" && opt3_name == "other-role" => {
+                let guild_id = get_guild_id(command);
+                let role_name = &command.data.resolved.roles[role_id].name;
+                let other_role_name = &command.data.resolved.roles[other_role_id].name;
+                let bundled = !get_boolean_option(options, "remove").unwrap_or(false);
+
+                if role_id == other_role_id {
+                    respond_to_command(
+                        ctx,
+                        command,
+                        "Members already get a role when they pick it".to_owned(),
+                    )
+                    .await;
+                    return;
+                }
+                if bundled {
+                    if let Err(problem) = check_roles(ctx, guild_id, [other_role_id.get()]).await
+                    {
+                        respond_to_command(ctx, command, problem).await;
+                        return;
+                    }
+                }
+
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+                match data.get_menu_mut(menu_name) {
+                    Some(menu)
+                        if bundled
+                            && menu.get_bundle(role_id.get()).len() >= BUNDLE_MAX_ROLES
+                            && !menu.get_bundle(role_id.get()).contains(&other_role_id.get()) =>
+                    {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!(
+                                "Picking {role_name} can give members at most {BUNDLE_MAX_ROLES} \
+                                 roles"
+                            ),
+                        )
+                        .await;
+                    }
+                    Some(menu) if menu.has_role(role_id.get()) => {
+                        // Listing members to update those who already hold the role can outlast
+                        // the initial response deadline
+                        if let Err(e) = command.defer_ephemeral(ctx).await {
+                            error!("Could not respond to command: {:?}", e);
+                            return;
+                        }
+                        menu.set_bundled(
+                            ctx,
+                            guild_id,
+                            role_id.get(),
+                            other_role_id.get(),
+                            bundled,
+                        )
+                        .await;
+                        let mut content = if bundled {
+                            format!(
+                                "Members who pick {role_name} from the {menu_name} menu will also \
+                                 get {other_role_name}"
+                            )
+                        } else {
+                            format!(
+                                "Members who pick {role_name} from the {menu_name} menu will no \
+                                 longer get {other_role_name} with it"
+                            )
+                        };
+                        data.commit();
+
+                        // Members are updated from the saved menus, so the guild's data is not
+                        // locked while paging through them
+                        let result = match db.get_guild_data(guild_id) {
+                            Some(data) => {
+                                let (role, other) = (*role_id, *other_role_id);
+                                reconcile_bundle(ctx, &data, guild_id, role, other, bundled)
+                                    .await
+                                    .map_err(|e| {
+                                        error!(
+                                            "Could not list the members of guild {:?}: {:?}",
+                                            guild_id, e
+                                        );
+                                    })
+                            }
+                            None => Err(()),
+                        };
+
+                        match (result, bundled) {
+                            (Ok(0), _) => {}
+                            (Ok(count), true) => write!(
+                                content,
+                                ". Gave {other_role_name} to {count} members who already hold \
+                                 {role_name}"
+                            )
+                            .expect("String concatenation success"),
+                            (Ok(count), false) => write!(
+                                content,
+                                ". Took {other_role_name} away from {count} members who do not \
+                                 get it from another pick"
+                            )
+                            .expect("String concatenation success"),
+                            (Err(()), _) => {
+                                write!(
+                                    content,
+                                    ". Could not update the members who already hold {role_name}"
+                                )
+                                .expect("String concatenation success");
+                            }
+                        }
+                        if let Err(e) = command
+                            .edit_response(ctx, EditInteractionResponse::new().content(content))
+                            .await
+                        {
+                            error!("Could not respond to command: {:?}", e);
+                        }
+                    }
+                    Some(_) => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("{role_name} is not enabled in the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                    None => {
+                        respond_to_command(
+                            ctx,
+                            command,
+                            format!("You have not configured any roles for the {menu_name} menu"),
+                        )
+                        .await;
+                    }
+                }
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+/// Gives a newly bundled role to the members who already hold the role it is bundled with, or
+/// takes a role dropped from the bundle away from those who no longer get it from any of their
+/// picks, returning how many members were updated
+async fn reconcile_bundle(
+    ctx: &Context,
+    data: &GuildData,
+    guild_id: GuildId,
+    role_id: RoleId,
+    other_role_id: RoleId,
+    bundled: bool,
+) -> serenity::Result<usize> {
+    let mut count = 0;
+    for member in get_members(ctx, guild_id).await? {
+        if !member.roles.contains(&role_id) {
+            continue;
+        }
+        let user_id = member.user.id;
+        let holds_other = member.roles.contains(&other_role_id);
+        let result = if bundled && !holds_other {
+            let reason = Some("Role bundled");
+            ctx.http
+                .add_member_role(guild_id, user_id, other_role_id, reason)
+                .await
+        } else if !bundled
+            && holds_other
+            && !data
+                .get_granted_roles(&member.roles)
+                .contains(&other_role_id.get())
+        {
+            let reason = Some("Role unbundled");
+            ctx.http
+                .remove_member_role(guild_id, user_id, other_role_id, reason)
+                .await
+        } else {
+            continue;
+        };
+        match result {
+            Ok(()) => count += 1,
+            Err(e) => error!("Could not update roles for user {:?}: {:?}", user_id, e),
+        }
+    }
+    Ok(count)
+}
+
+/// Explains why the bot could not post the menu in the channel, or hand out its roles
+async fn check_can_post(
+    ctx: &Context,
+    guild_id: GuildId,
+    menu: &RoleMenu,
+    channel_id: ChannelId,
+) -> Result<(), String> {
+    check_roles(ctx, guild_id, menu.get_granted_role_ids()).await?;
+    let required = required_channel_permissions(menu.get_style());
+    match find_missing_channel_permissions(ctx, guild_id, channel_id, required).await {
+        Ok(missing) if missing.is_empty() => Ok(()),
+        Ok(missing) => Err(format!(
+            "The bot needs the {} permissions in <#{channel_id}> to post the menu",
+            missing.get_permission_names().join(", ")
+        )),
+        Err(e) => {
+            error!(
+                "Could not check permissions in channel {:?}: {:?}",
+                channel_id, e
+            );
+            Err(format!(
+                "Could not check the bot's permissions in <#{channel_id}>"
+            ))
+        }
+    }
+}
+
+/// Explains why the bot could not hand out one of the roles, if it could not
+async fn check_roles(
+    ctx: &Context,
+    guild_id: GuildId,
+    role_ids: impl IntoIterator<Item = u64>,
+) -> Result<(), String> {
+    let hierarchy = RoleHierarchy::fetch(ctx, guild_id).await.map_err(|e| {
+        error!("Could not fetch roles for guild {:?}: {:?}", guild_id, e);
+        "Could not look up the server's roles".to_owned()
+    })?;
+    for role_id in role_ids {
+        if let Some(problem) = hierarchy.check(role_id) {
+            return Err(problem.describe(&format!("<@&{role_id}>")));
+        }
+    }
+    Ok(())
+}
+
+pub async fn set_window(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        match options.first() {
+            Some(CommandDataOption {
+                name,
+                value: CommandDataOptionValue::String(menu_name),
+                ..
+            }) if name == "menu" => {
+                let guild_id = get_guild_id(command);
+                let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+                    return;
+                };
+                let Some(menu) = data.get_menu_mut(menu_name) else {
+                    respond_to_command(
+                        ctx,
+                        command,
+                        format!("You have not configured any roles for the {menu_name} menu"),
+                    )
+                    .await;
+                    return;
+                };
+
+                // Times that were not given keep their current value
+                let mut times = if get_boolean_option(options, "clear").unwrap_or(false) {
+                    [None, None]
+                } else {
+                    let window = menu.get_window();
+                    [window.get_opens_at(), window.get_closes_at()]
+                };
+                for (time, name) in times.iter_mut().zip(["opens", "closes"]) {
+                    if let Some(input) = get_string_option(options, name) {
+                        let Some(parsed) = parse_time(input) else {
+                            respond_to_command(
+                                ctx,
+                                command,
+                                format!(
+                                    "{input} is not a time, give a Discord timestamp such as \
+                                     <t:1717264800:F> or a date such as 2024-06-01T18:00:00Z"
+                                ),
+                            )
+                            .await;
+                            return;
+                        };
+                        *time = Some(parsed);
+                    }
+                }
+                let [opens_at, closes_at] = times;
+                if let (Some(opens_at), Some(closes_at)) = (opens_at, closes_at) {
+                    if opens_at.unix_timestamp() >= closes_at.unix_timestamp() {
+                        respond_to_command(ctx, command, "The menu must open before it closes")
+                            .await;
+                        return;
+                    }
+                }
+
+                menu.set_window(ctx, guild_id, MenuWindow::new(opens_at, closes_at))
+                    .await;
+                data.commit();
+
+                let content = match (opens_at, closes_at) {
+                    (None, None) => {
+                        format!("Members can pick roles from the {menu_name} menu at any time")
+                    }
+                    (Some(opens_at), None) => format!(
+                        "The {menu_name} menu opens <t:{}:F>",
+                        opens_at.unix_timestamp()
+                    ),
+                    (None, Some(closes_at)) => format!(
+                        "The {menu_name} menu closes <t:{}:F>",
+                        closes_at.unix_timestamp()
+                    ),
+                    (Some(opens_at), Some(closes_at)) => format!(
+                        "The {menu_name} menu opens <t:{}:F> and closes <t:{}:F>",
+                        opens_at.unix_timestamp(),
+                        closes_at.unix_timestamp()
+                    ),
+                };
+                respond_to_command(ctx, command, content).await;
+            }
+            _ => warn!("A command was invoked with unexpected arguments, Discord should have prevented this"),
+        }
+    }
+}
+
+/// Reads a time given as a Discord timestamp such as <t:1717264800:F>, a Unix timestamp or an
+/// RFC 3339 date such as 2024-06-01T18:00:00Z
+fn parse_time(input: &str) -> Option<Timestamp> {
+    let input = input.trim();
+    let seconds = input
+        .strip_prefix("<t:")
+        .and_then(|rest| rest.strip_suffix('>'))
+        .and_then(|rest| rest.split(':').next())
+        .unwrap_or(input);
+    match seconds.parse::<i64>() {
+        Ok(seconds) => Timestamp::from_unix_timestamp(seconds).ok(),
+        Err(_) => Timestamp::parse(input).ok(),
+    }
+}
+
+pub async fn set_log_channel(
+    ctx: &Context,
+    db: &Database,
+    command: &CommandInteraction,
+    opt: &CommandDataOption,
+) {
+    if let CommandDataOptionValue::SubCommand(options) = &opt.value {
+        let guild_id = get_guild_id(command);
+        let channel_id = get_channel_option(options, "channel");
+        let Some(mut data) = begin_change(ctx, db, command, guild_id).await else {
+            return;
+        };
+        data.set_log_channel(channel_id);
+        data.commit();
+
+        let content = match channel_id {
+            Some(channel_id) => format!("Role changes will now be logged in <#{channel_id}>"),
+            None => "Role changes will no longer be logged".to_owned(),
+        };
+        respond_to_command(ctx, command, content).await;
     }
 }
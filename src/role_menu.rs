@@ -0,0 +1,1349 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    str::FromStr,
+};
+
+use bimap::BiMap;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    all::{
+        ButtonStyle, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditMessage, GuildId, Role,
+        RoleId, UserId,
+    },
+    model::{
+        prelude::{ChannelId, EmojiId, MessageId, ReactionType},
+        Color, Timestamp,
+    },
+    prelude::Context,
+};
+
+/// Prefix of the custom ID given to each role's button, followed by the role ID
+pub const TOGGLE_ROLE_PREFIX: &str = "toggle-role:";
+
+/// Custom ID of the dropdown listing a select-style menu's roles
+pub const SELECT_ROLES_ID: &str = "select-roles";
+
+/// Discord allows at most five buttons in a single action row
+const BUTTONS_PER_ROW: usize = 5;
+
+/// Discord allows at most five action rows of buttons on a message
+const BUTTONS_PER_MESSAGE: usize = BUTTONS_PER_ROW * 5;
+
+/// Discord allows at most 20 different reactions on a message
+const REACTIONS_PER_MESSAGE: usize = 20;
+
+/// Discord allows at most 25 options in a dropdown
+const OPTIONS_PER_SELECT: usize = 25;
+
+/// Discord rejects button labels longer than this, though role names may be longer
+const BUTTON_LABEL_MAX_LENGTH: usize = 80;
+
+/// Discord rejects embed field values longer than this
+const FIELD_MAX_LENGTH: usize = 1024;
+
+/// Discord rejects embeds whose title, description, field names and values and footer add up to
+/// more than this
+const EMBED_MAX_LENGTH: usize = 6000;
+
+/// The most text an admin may give a menu's title, description and footer combined, leaving the
+/// rest of the embed for its roles
+pub const APPEARANCE_MAX_LENGTH: usize = 4000;
+
+/// Most roles a single pick from a menu can grant, counting the picked role
+pub const BUNDLE_MAX_ROLES: usize = 10;
+
+/// Shown in place of the embed while a menu has no roles
+const NO_ROLES_CONTENT: &str = "No configured roles to display";
+
+/// Name of the embed field listing a menu's roles
+const ROLES_FIELD_NAME: &str = "Self-Assignable Roles";
+
+/// Fields continuing the list of roles are left blank, but Discord requires them to be named
+const CONTINUED_FIELD_NAME: &str = "\u{200b}";
+
+/// How members pick roles from a menu
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MenuStyle {
+    #[default]
+    Reactions,
+    Buttons,
+    Select,
+}
+
+impl FromStr for MenuStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reactions" => Ok(Self::Reactions),
+            "buttons" => Ok(Self::Buttons),
+            "select" => Ok(Self::Select),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Other roles a member must, or must not, hold to take a self-service role
+#[derive(Default, Serialize, Deserialize)]
+pub struct RoleRequirements {
+    required: BTreeSet<u64>,
+    blocked: BTreeSet<u64>,
+}
+
+/// Why a member may not take a self-service role
+pub enum UnmetRequirement {
+    Missing(u64),
+    Blocked(u64),
+}
+
+impl UnmetRequirement {
+    pub fn get_role_id(&self) -> u64 {
+        match self {
+            Self::Missing(role_id) | Self::Blocked(role_id) => *role_id,
+        }
+    }
+
+    /// Explains the requirement to the member, naming the other role however suits the message
+    pub fn describe(&self, role: &str) -> String {
+        match self {
+            Self::Missing(_) => format!("you need the {role} role to pick it"),
+            Self::Blocked(_) => format!("members with the {role} role cannot pick it"),
+        }
+    }
+}
+
+/// How a menu's embed looks, where admins have chosen something other than the default
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MenuAppearance {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<u32>,
+    pub footer: Option<String>,
+    pub thumbnail: Option<String>,
+    pub image: Option<String>,
+}
+
+/// What enabling a role with an emoji would overwrite in a menu
+pub enum RoleConflict {
+    /// The role is already enabled with another emoji
+    Remapped(ReactionType),
+    /// Another role already uses the emoji
+    EmojiTaken(u64),
+}
+
+impl RoleConflict {
+    pub fn describe(&self, role_id: u64, emoji: &ReactionType) -> String {
+        match self {
+            Self::Remapped(previous) => format!(
+                "<@&{role_id}> already uses {previous}. It will switch to {emoji} and the old \
+                 reactions will be removed. Members keep the role, but a sync will take it away \
+                 from those who have not reacted with {emoji}."
+            ),
+            Self::EmojiTaken(owner) => format!(
+                "{emoji} is already used by <@&{owner}>, which will be removed from the menu \
+                 along with its reactions. Members keep the role."
+            ),
+        }
+    }
+}
+
+/// Whether a menu with a window is taking picks
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowState {
+    NotYetOpen,
+    Open,
+    Closed,
+}
+
+/// When a menu takes picks, for menus that only run for a while such as event signups
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MenuWindow {
+    opens_at: Option<Timestamp>,
+    closes_at: Option<Timestamp>,
+    /// The state the menu's messages were last drawn in, so they are redrawn once it changes
+    shown: Option<WindowState>,
+}
+
+impl MenuWindow {
+    pub fn new(opens_at: Option<Timestamp>, closes_at: Option<Timestamp>) -> Self {
+        Self {
+            opens_at,
+            closes_at,
+            shown: None,
+        }
+    }
+
+    pub fn get_opens_at(&self) -> Option<Timestamp> {
+        self.opens_at
+    }
+
+    pub fn get_closes_at(&self) -> Option<Timestamp> {
+        self.closes_at
+    }
+
+    pub fn get_state(&self, now: Timestamp) -> WindowState {
+        let now = now.unix_timestamp();
+        if self
+            .opens_at
+            .is_some_and(|opens_at| now < opens_at.unix_timestamp())
+        {
+            WindowState::NotYetOpen
+        } else if self
+            .closes_at
+            .is_some_and(|closes_at| now >= closes_at.unix_timestamp())
+        {
+            WindowState::Closed
+        } else {
+            WindowState::Open
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.opens_at.is_some() || self.closes_at.is_some()
+    }
+}
+
+/// A set of roles within a menu, of which members may be required to hold at most one
+#[derive(Default, Serialize, Deserialize)]
+pub struct RoleGroup {
+    roles: BTreeSet<u64>,
+    exclusive: bool,
+}
+
+/// What members get by picking one of a menu's emoji: the role the menu lists the choice as,
+/// along with any other roles granted and taken away together with it
+#[derive(Serialize, Deserialize)]
+pub struct RoleBundle {
+    emoji: ReactionType,
+    role_id: u64,
+    #[serde(default)]
+    extra_roles: BTreeSet<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct RoleMenu {
+    channel_id: Option<ChannelId>,
+    message_id: Option<MessageId>,
+    /// Further messages the menu continues onto when its roles do not fit in one
+    #[serde(default)]
+    overflow_message_ids: Vec<MessageId>,
+    /// What picking each of the menu's emoji grants
+    #[serde(default)]
+    bundles: Vec<RoleBundle>,
+    /// How menus stored before bundles existed mapped each role to its own emoji, which
+    /// [`Self::migrate_roles_to_emoji`] turns into bundles
+    #[serde(default, rename = "roles_to_emoji", skip_serializing)]
+    legacy_roles_to_emoji: BiMap<u64, ReactionType>,
+    /// The order roles are listed in, as roles added before the menu kept one have none
+    #[serde(default)]
+    order: Vec<u64>,
+    #[serde(default)]
+    style: MenuStyle,
+    #[serde(default)]
+    min_choices: u8,
+    #[serde(default)]
+    max_choices: Option<u8>,
+    #[serde(default)]
+    exclusive: bool,
+    #[serde(default)]
+    groups: BTreeMap<String, RoleGroup>,
+    #[serde(default)]
+    requirements: BTreeMap<u64, RoleRequirements>,
+    #[serde(default)]
+    appearance: MenuAppearance,
+    /// Explanations of what each role is for, shown beside its emoji
+    #[serde(default)]
+    descriptions: BTreeMap<u64, String>,
+    /// Members who kept each role when the menu was moved, and so hold it without a reaction
+    #[serde(default)]
+    carried_over: BTreeMap<u64, BTreeSet<u64>>,
+    /// How many seconds members keep each temporary role before it is taken away again
+    #[serde(default)]
+    durations: BTreeMap<u64, u64>,
+    #[serde(default)]
+    window: MenuWindow,
+}
+
+impl RoleMenu {
+    /// Rebuilds the single menu of a guild configured before named menus existed
+    pub fn from_legacy(
+        channel_id: Option<ChannelId>,
+        message_id: Option<MessageId>,
+        roles_to_emoji: BiMap<u64, ReactionType>,
+    ) -> Self {
+        let mut menu = Self {
+            channel_id,
+            message_id,
+            legacy_roles_to_emoji: roles_to_emoji,
+            ..Self::default()
+        };
+        menu.migrate_roles_to_emoji();
+        menu
+    }
+
+    /// Gives each role of a menu stored before bundles existed a bundle of its own
+    pub fn migrate_roles_to_emoji(&mut self) {
+        for (role_id, emoji) in std::mem::take(&mut self.legacy_roles_to_emoji) {
+            if !self.has_role(role_id) {
+                self.bundles.push(RoleBundle {
+                    emoji,
+                    role_id,
+                    extra_roles: BTreeSet::new(),
+                });
+            }
+        }
+    }
+
+    pub async fn send_message(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    ) -> &Self {
+        if !self.message_exists(ctx, channel_id).await {
+            self.window.shown = Some(self.window.get_state(Timestamp::now()));
+            let pages = self.generate_pages();
+            let roles = self.fetch_roles(ctx, guild_id).await;
+            let mut message_ids = Vec::new();
+            for index in 0..pages.len() {
+                match self.post_page(ctx, channel_id, &pages, index, &roles).await {
+                    Ok(message_id) => message_ids.push(message_id),
+                    Err(e) => {
+                        error!("Could not send message: {:?}", e);
+                        break;
+                    }
+                }
+            }
+
+            if !message_ids.is_empty() {
+                self.channel_id = Some(channel_id);
+            }
+            self.set_message_ids(message_ids);
+        }
+        self
+    }
+
+    pub async fn add_role(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        role_id: u64,
+        emoji: ReactionType,
+    ) {
+        let mut cleared_emoji: Vec<ReactionType> = self
+            .get_emoji(role_id)
+            .filter(|previous| **previous != emoji)
+            .cloned()
+            .into_iter()
+            .collect();
+        if let Some(owner) = self
+            .get_role(&emoji)
+            .copied()
+            .filter(|owner| *owner != role_id)
+        {
+            // Reactions for the evicted role would otherwise count as picks of this one
+            cleared_emoji.push(emoji.clone());
+            self.set_role_group(owner, None);
+            self.requirements.remove(&owner);
+            self.carried_over.remove(&owner);
+            self.descriptions.remove(&owner);
+            self.durations.remove(&owner);
+            self.bundles.retain(|bundle| bundle.role_id != owner);
+            self.order.retain(|role| *role != owner);
+        }
+
+        if !self.order.contains(&role_id) {
+            self.order.push(role_id);
+        }
+        match self
+            .bundles
+            .iter_mut()
+            .find(|bundle| bundle.role_id == role_id)
+        {
+            Some(bundle) => bundle.emoji = emoji,
+            None => self.bundles.push(RoleBundle {
+                emoji,
+                role_id,
+                extra_roles: BTreeSet::new(),
+            }),
+        }
+        self.update_message(ctx, guild_id, &cleared_emoji).await;
+    }
+
+    /// Finds what enabling the role with the emoji would overwrite, since each role has a single
+    /// emoji and each emoji a single role
+    pub fn find_conflicts(&self, role_id: u64, emoji: &ReactionType) -> Vec<RoleConflict> {
+        let mut conflicts = Vec::new();
+        if let Some(previous) = self
+            .get_emoji(role_id)
+            .filter(|previous| *previous != emoji)
+        {
+            conflicts.push(RoleConflict::Remapped(previous.clone()));
+        }
+        if let Some(owner) = self.get_role(emoji).filter(|owner| **owner != role_id) {
+            conflicts.push(RoleConflict::EmojiTaken(*owner));
+        }
+        conflicts
+    }
+
+    pub async fn remove_role(&mut self, ctx: &Context, guild_id: GuildId, role_id: u64) {
+        let (removed, kept) = std::mem::take(&mut self.bundles)
+            .into_iter()
+            .partition(|bundle| bundle.role_id == role_id);
+        self.bundles = kept;
+        let emoji: Vec<ReactionType> = removed.into_iter().map(|bundle| bundle.emoji).collect();
+        self.set_role_group(role_id, None);
+        self.requirements.remove(&role_id);
+        self.carried_over.remove(&role_id);
+        self.descriptions.remove(&role_id);
+        self.durations.remove(&role_id);
+        self.order.retain(|role| *role != role_id);
+        self.update_message(ctx, guild_id, &emoji).await;
+    }
+
+    /// Makes taking the role depend on the member holding, or not holding, another role.
+    /// Passing neither flag clears any requirement between the two roles.
+    pub fn set_requirement(
+        &mut self,
+        role_id: u64,
+        other_role_id: u64,
+        required: bool,
+        blocked: bool,
+    ) {
+        let requirements = self.requirements.entry(role_id).or_default();
+        requirements.required.remove(&other_role_id);
+        requirements.blocked.remove(&other_role_id);
+
+        if required {
+            requirements.required.insert(other_role_id);
+        } else if blocked {
+            requirements.blocked.insert(other_role_id);
+        }
+        if requirements.required.is_empty() && requirements.blocked.is_empty() {
+            self.requirements.remove(&role_id);
+        }
+    }
+
+    /// Drops every requirement on a role that no longer exists, returning the roles that
+    /// required it and so can no longer be picked as configured
+    pub fn forget_requirement_role(&mut self, role_id: u64) -> Vec<u64> {
+        let mut required_by = Vec::new();
+        for (menu_role, requirements) in &mut self.requirements {
+            if requirements.required.remove(&role_id) {
+                required_by.push(*menu_role);
+            }
+            requirements.blocked.remove(&role_id);
+        }
+        self.requirements.retain(|_, requirements| {
+            !requirements.required.is_empty() || !requirements.blocked.is_empty()
+        });
+        required_by
+    }
+
+    /// Deletes the menu's messages that still exist, so the menu can be posted again
+    pub async fn delete_message(&mut self, ctx: &Context) -> serenity::Result<()> {
+        if let Some(channel_id) = self.channel_id {
+            let message_ids: Vec<MessageId> = self.get_message_ids().collect();
+            for message_id in message_ids {
+                if ctx.http.get_message(channel_id, message_id).await.is_ok() {
+                    channel_id.delete_message(ctx, message_id).await?;
+                }
+            }
+        }
+        self.clear_message();
+        Ok(())
+    }
+
+    /// Records the members who keep their roles from the menu's previous message
+    pub fn set_carried_over(&mut self, carried_over: BTreeMap<u64, BTreeSet<u64>>) {
+        self.carried_over = carried_over;
+    }
+
+    pub fn get_carried_over(&self) -> &BTreeMap<u64, BTreeSet<u64>> {
+        &self.carried_over
+    }
+
+    pub fn is_carried_over(&self, role_id: u64, user_id: UserId) -> bool {
+        self.carried_over
+            .get(&role_id)
+            .is_some_and(|members| members.contains(&user_id.get()))
+    }
+
+    /// Forgets the menu's messages after they or their channel were deleted
+    pub fn clear_message(&mut self) {
+        self.channel_id = None;
+        self.message_id = None;
+        self.overflow_message_ids.clear();
+    }
+
+    /// Finds the first requirement the member does not meet to take the role, if any
+    pub fn check_requirements(
+        &self,
+        member_roles: &[RoleId],
+        role_id: u64,
+    ) -> Option<UnmetRequirement> {
+        self.requirements.get(&role_id).and_then(|requirements| {
+            requirements
+                .required
+                .iter()
+                .find(|required| !member_roles.contains(&RoleId::new(**required)))
+                .map(|required| UnmetRequirement::Missing(*required))
+                .or_else(|| {
+                    requirements
+                        .blocked
+                        .iter()
+                        .find(|blocked| member_roles.contains(&RoleId::new(**blocked)))
+                        .map(|blocked| UnmetRequirement::Blocked(*blocked))
+                })
+        })
+    }
+
+    /// Moves the role into the named group, or out of any group
+    pub fn set_role_group(&mut self, role_id: u64, group: Option<&str>) {
+        self.groups.values_mut().for_each(|group| {
+            group.roles.remove(&role_id);
+        });
+        if let Some(name) = group {
+            self.groups
+                .entry(name.to_owned())
+                .or_default()
+                .roles
+                .insert(role_id);
+        }
+    }
+
+    /// Makes the named group, or the whole menu, allow members to hold only one of its roles
+    pub async fn set_exclusive(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        group: Option<&str>,
+        exclusive: bool,
+    ) {
+        match group {
+            Some(name) => self.groups.entry(name.to_owned()).or_default().exclusive = exclusive,
+            None => self.exclusive = exclusive,
+        }
+        // A select menu's choice limits depend on whether the whole menu is exclusive
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    /// Finds the other roles a member must give up when taking this one
+    pub fn get_exclusive_peers(&self, role_id: u64) -> BTreeSet<u64> {
+        if self.exclusive {
+            self.bundles
+                .iter()
+                .map(|bundle| bundle.role_id)
+                .filter(|peer| *peer != role_id)
+                .collect()
+        } else {
+            self.groups
+                .values()
+                .filter(|group| group.exclusive && group.roles.contains(&role_id))
+                .flat_map(|group| group.roles.iter().copied())
+                .filter(|peer| *peer != role_id && self.has_role(*peer))
+                .collect()
+        }
+    }
+
+    /// Switches how members pick roles, swapping the messages' reactions and components to match
+    pub async fn set_style(&mut self, ctx: &Context, guild_id: GuildId, style: MenuStyle) {
+        let previous = std::mem::replace(&mut self.style, style);
+        self.update_message(ctx, guild_id, &[]).await;
+
+        if let (Some(channel_id), true) = (
+            self.channel_id,
+            style != MenuStyle::Reactions && previous == MenuStyle::Reactions,
+        ) {
+            for message_id in self.get_message_ids() {
+                if let Err(e) = ctx
+                    .http
+                    .delete_message_reactions(channel_id, message_id)
+                    .await
+                {
+                    error!(
+                        "Could not remove reactions to message for channel {:?}: {:?}",
+                        self.channel_id, e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Moves the role to a place in the menu's list, counting from zero
+    pub async fn move_role(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        role_id: u64,
+        index: usize,
+    ) {
+        let mut order = self.get_role_ids();
+        order.retain(|role| *role != role_id);
+        order.insert(index.min(order.len()), role_id);
+        self.set_order(ctx, guild_id, order).await;
+    }
+
+    /// Lists the menu's roles in the given order
+    pub async fn set_order(&mut self, ctx: &Context, guild_id: GuildId, order: Vec<u64>) {
+        self.order = order;
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    /// Explains what the role is for in the menu's message, or removes the explanation
+    pub async fn set_description(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        role_id: u64,
+        description: Option<String>,
+    ) {
+        match description {
+            Some(description) => self.descriptions.insert(role_id, description),
+            None => self.descriptions.remove(&role_id),
+        };
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    pub async fn set_appearance(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        appearance: MenuAppearance,
+    ) {
+        self.appearance = appearance;
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    /// Limits how many roles a member may pick at once from a select-style menu
+    pub fn set_choice_limits(&mut self, min_choices: Option<u8>, max_choices: Option<u8>) {
+        if let Some(min) = min_choices {
+            self.min_choices = min;
+        }
+        if max_choices.is_some() {
+            self.max_choices = max_choices;
+        }
+    }
+
+    /// Caps how many of the menu's roles a member may hold at once, or lifts the cap
+    pub async fn set_max_choices(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        max_choices: Option<u8>,
+    ) {
+        self.max_choices = max_choices;
+        // A select menu's choice limits are rendered into the message
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    pub fn get_max_choices(&self) -> Option<u8> {
+        self.max_choices
+    }
+
+    /// Whether taking this role would leave the member holding more of the menu's roles than
+    /// allowed, after giving up any roles it excludes
+    pub fn would_exceed_limit(&self, member_roles: &[RoleId], role_id: u64) -> bool {
+        self.max_choices.is_some_and(|max| {
+            let peers = self.get_exclusive_peers(role_id);
+            let held = self
+                .get_picked_roles(member_roles)
+                .into_iter()
+                .filter(|role| *role != role_id && !peers.contains(role))
+                .count();
+            held >= usize::from(max)
+        })
+    }
+
+    /// The menu's roles the member holds, leaving out those they only hold because another of
+    /// their roles from the menu bundles it
+    pub fn get_picked_roles(&self, member_roles: &[RoleId]) -> Vec<u64> {
+        let held: Vec<&RoleBundle> = self
+            .bundles
+            .iter()
+            .filter(|bundle| member_roles.contains(&RoleId::new(bundle.role_id)))
+            .collect();
+        held.iter()
+            .filter(|bundle| {
+                !held.iter().any(|other| {
+                    other.role_id != bundle.role_id && other.extra_roles.contains(&bundle.role_id)
+                })
+            })
+            .map(|bundle| bundle.role_id)
+            .collect()
+    }
+
+    /// Whether the member holds any of the roles picking the role grants
+    pub fn holds_bundle(&self, member_roles: &[RoleId], role_id: u64) -> bool {
+        self.get_bundle(role_id)
+            .into_iter()
+            .any(|role| member_roles.contains(&RoleId::new(role)))
+    }
+
+    /// Limits when members may pick roles from the menu, redrawing it to show the new times
+    pub async fn set_window(&mut self, ctx: &Context, guild_id: GuildId, window: MenuWindow) {
+        self.window = window;
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    pub fn get_window(&self) -> &MenuWindow {
+        &self.window
+    }
+
+    /// Explains why members cannot pick roles from the menu right now, if they cannot
+    pub fn describe_closed(&self) -> Option<String> {
+        match self.window.get_state(Timestamp::now()) {
+            WindowState::NotYetOpen => self
+                .window
+                .opens_at
+                .map(|opens_at| format!("the menu opens <t:{}:R>", opens_at.unix_timestamp())),
+            WindowState::Open => None,
+            WindowState::Closed => Some("the menu has closed".to_owned()),
+        }
+    }
+
+    /// Whether the menu's messages still show an earlier state of its window, since opening or
+    /// closing changes what they say
+    pub fn is_window_outdated(&self) -> bool {
+        self.window.is_set()
+            && self.message_id.is_some()
+            && self.window.shown != Some(self.window.get_state(Timestamp::now()))
+    }
+
+    /// Redraws the menu's messages, such as when its window opens or closes
+    pub async fn refresh_message(&mut self, ctx: &Context, guild_id: GuildId) {
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    /// Adds another role to those members get along with the role, or takes it out of the bundle
+    pub async fn set_bundled(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        role_id: u64,
+        other_role_id: u64,
+        bundled: bool,
+    ) {
+        let Some(bundle) = self
+            .bundles
+            .iter_mut()
+            .find(|bundle| bundle.role_id == role_id)
+        else {
+            return;
+        };
+        if bundled {
+            bundle.extra_roles.insert(other_role_id);
+        } else {
+            bundle.extra_roles.remove(&other_role_id);
+        }
+        self.update_message(ctx, guild_id, &[]).await;
+    }
+
+    /// Takes a role that no longer exists out of every bundle, returning whether any had it
+    pub async fn forget_bundled_role(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        role_id: u64,
+    ) -> bool {
+        let mut found = false;
+        for bundle in &mut self.bundles {
+            found |= bundle.extra_roles.remove(&role_id);
+        }
+        if found {
+            self.update_message(ctx, guild_id, &[]).await;
+        }
+        found
+    }
+
+    /// Every role members get by picking the role, starting with the role itself
+    pub fn get_bundle(&self, role_id: u64) -> Vec<u64> {
+        std::iter::once(role_id)
+            .chain(
+                self.bundles
+                    .iter()
+                    .filter(|bundle| bundle.role_id == role_id)
+                    .flat_map(|bundle| bundle.extra_roles.iter().copied())
+                    .filter(|other| *other != role_id),
+            )
+            .collect()
+    }
+
+    /// Every role the menu hands out, including those bundled with its roles
+    pub fn get_granted_role_ids(&self) -> BTreeSet<u64> {
+        self.bundles
+            .iter()
+            .flat_map(|bundle| {
+                std::iter::once(bundle.role_id).chain(bundle.extra_roles.iter().copied())
+            })
+            .collect()
+    }
+
+    /// Makes members lose the role again some number of seconds after picking it, or keep it
+    pub fn set_duration(&mut self, role_id: u64, seconds: Option<u64>) {
+        match seconds {
+            Some(seconds) => self.durations.insert(role_id, seconds),
+            None => self.durations.remove(&role_id),
+        };
+    }
+
+    pub fn get_duration(&self, role_id: u64) -> Option<u64> {
+        self.durations.get(&role_id).copied()
+    }
+
+    /// The role the menu lists the emoji's bundle as
+    pub fn get_role(&self, emoji: &ReactionType) -> Option<&u64> {
+        self.bundles
+            .iter()
+            .find(|bundle| bundle.emoji == *emoji)
+            .map(|bundle| &bundle.role_id)
+    }
+
+    pub fn get_emoji(&self, role_id: u64) -> Option<&ReactionType> {
+        self.bundles
+            .iter()
+            .find(|bundle| bundle.role_id == role_id)
+            .map(|bundle| &bundle.emoji)
+    }
+
+    pub fn get_emojis(&self) -> impl Iterator<Item = &ReactionType> {
+        self.bundles.iter().map(|bundle| &bundle.emoji)
+    }
+
+    /// Whether the menu lists the role, rather than only bundling it with another
+    pub fn has_role(&self, role_id: u64) -> bool {
+        self.bundles.iter().any(|bundle| bundle.role_id == role_id)
+    }
+
+    /// The menu's roles in the order they are listed. Roles from before the menu kept an order
+    /// follow the rest, ordered by ID.
+    pub fn get_role_ids(&self) -> Vec<u64> {
+        let mut unordered: Vec<u64> = self
+            .bundles
+            .iter()
+            .map(|bundle| bundle.role_id)
+            .filter(|role_id| !self.order.contains(role_id))
+            .collect();
+        unordered.sort_unstable();
+
+        self.order
+            .iter()
+            .copied()
+            .filter(|role_id| self.has_role(*role_id))
+            .chain(unordered)
+            .collect()
+    }
+
+    /// Finds the roles whose custom emoji is not among the given, still existing emoji
+    pub fn find_roles_with_missing_emoji(&self, exists: impl Fn(EmojiId) -> bool) -> Vec<u64> {
+        self.bundles
+            .iter()
+            .filter_map(|bundle| match bundle.emoji {
+                ReactionType::Custom { id, .. } if !exists(id) => Some(bundle.role_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_channel_id(&self) -> Option<ChannelId> {
+        self.channel_id
+    }
+
+    /// The first of the menu's messages, which links to the menu
+    pub fn get_message_id(&self) -> Option<MessageId> {
+        self.message_id
+    }
+
+    /// Every message the menu is spread across, in order
+    pub fn get_message_ids(&self) -> impl Iterator<Item = MessageId> + '_ {
+        self.message_id
+            .into_iter()
+            .chain(self.overflow_message_ids.iter().copied())
+    }
+
+    pub fn has_message(&self, message_id: MessageId) -> bool {
+        self.get_message_ids().any(|id| id == message_id)
+    }
+
+    /// The roles shown on one of the menu's messages
+    pub fn get_page_roles(&self, message_id: MessageId) -> Vec<u64> {
+        self.get_message_ids()
+            .position(|id| id == message_id)
+            .and_then(|index| self.generate_pages().into_iter().nth(index))
+            .unwrap_or_default()
+    }
+
+    /// The message the role is currently shown on
+    pub fn get_role_message_id(&self, role_id: u64) -> Option<MessageId> {
+        self.generate_pages()
+            .iter()
+            .position(|page| page.contains(&role_id))
+            .and_then(|index| self.get_message_ids().nth(index))
+    }
+
+    pub fn get_style(&self) -> MenuStyle {
+        self.style
+    }
+
+    pub fn get_appearance(&self) -> &MenuAppearance {
+        &self.appearance
+    }
+
+    fn set_message_ids(&mut self, message_ids: Vec<MessageId>) {
+        let mut message_ids = message_ids.into_iter();
+        self.message_id = message_ids.next();
+        self.overflow_message_ids = message_ids.collect();
+    }
+
+    /// Brings every one of the menu's messages in line with its roles, continuing onto new
+    /// messages when the roles no longer fit and deleting the messages no longer needed.
+    /// Reactions with the cleared emoji, such as ones the menu stopped using or gave to another
+    /// role, are removed from every message.
+    async fn update_message(
+        &mut self,
+        ctx: &Context,
+        guild_id: GuildId,
+        cleared_emoji: &[ReactionType],
+    ) {
+        let (Some(channel_id), Some(_)) = (self.channel_id, self.message_id) else {
+            return;
+        };
+        self.window.shown = Some(self.window.get_state(Timestamp::now()));
+        let pages = self.generate_pages();
+        let roles = self.fetch_roles(ctx, guild_id).await;
+        let mut message_ids: Vec<MessageId> = self.get_message_ids().collect();
+
+        for index in 0..pages.len() {
+            let result = match message_ids.get(index).copied() {
+                Some(message_id) => channel_id
+                    .edit_message(ctx, message_id, {
+                        let result = EditMessage::new()
+                            .components(self.generate_components(&pages, index, &roles));
+
+                        match self.generate_embed(&pages, index) {
+                            Some(embed) => result.embed(embed).content(""),
+                            None => result.embeds(Vec::new()).content(NO_ROLES_CONTENT),
+                        }
+                    })
+                    .await
+                    .map(|_| ()),
+                None => self
+                    .post_page(ctx, channel_id, &pages, index, &roles)
+                    .await
+                    .map(|message_id| message_ids.push(message_id)),
+            };
+            if let Err(e) = result {
+                error!(
+                    "Could not edit message for channel {:?}: {:?}",
+                    self.channel_id, e
+                );
+                break;
+            }
+        }
+
+        if message_ids.len() > pages.len() {
+            for message_id in message_ids.split_off(pages.len()) {
+                if let Err(e) = channel_id.delete_message(ctx, message_id).await {
+                    error!(
+                        "Could not delete message for channel {:?}: {:?}",
+                        self.channel_id, e
+                    );
+                }
+            }
+        }
+        self.set_message_ids(message_ids);
+
+        if self.style == MenuStyle::Reactions {
+            self.update_reactions(ctx, channel_id, &pages, cleared_emoji)
+                .await;
+        }
+    }
+
+    /// Makes the bot's reactions on each message match the roles shown on it. Members' reactions
+    /// left behind on another message when a role moves between messages still count for it.
+    async fn update_reactions(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        pages: &[Vec<u64>],
+        cleared_emoji: &[ReactionType],
+    ) {
+        for (message_id, page) in self.get_message_ids().zip(pages) {
+            let message = match channel_id.message(ctx, message_id).await {
+                Ok(message) => message,
+                Err(e) => {
+                    error!(
+                        "Could not fetch message for channel {:?}: {:?}",
+                        self.channel_id, e
+                    );
+                    continue;
+                }
+            };
+
+            for reaction in &message.reactions {
+                let emoji = &reaction.reaction_type;
+                let result = if cleared_emoji.contains(emoji) {
+                    message.delete_reaction_emoji(ctx, emoji.clone()).await
+                } else if reaction.me
+                    && self
+                        .get_role(emoji)
+                        .is_some_and(|role_id| !page.contains(role_id))
+                {
+                    message.delete_reaction(ctx, None, emoji.clone()).await
+                } else {
+                    Ok(())
+                };
+                if let Err(e) = result {
+                    error!(
+                        "Could not remove reactions to message for channel {:?}: {:?}",
+                        self.channel_id, e
+                    );
+                }
+            }
+
+            for role_id in page {
+                let emoji = self
+                    .get_emoji(*role_id)
+                    .expect("Every enabled role has an emoji");
+                let reacted = !cleared_emoji.contains(emoji)
+                    && message
+                        .reactions
+                        .iter()
+                        .any(|reaction| reaction.me && reaction.reaction_type == *emoji);
+                if !reacted {
+                    if let Err(e) = message.react(ctx, emoji.clone()).await {
+                        error!(
+                            "Could not react to message for channel {:?}: {:?}",
+                            self.channel_id, e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    async fn post_page(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        pages: &[Vec<u64>],
+        index: usize,
+        roles: &HashMap<RoleId, Role>,
+    ) -> serenity::Result<MessageId> {
+        let result = CreateMessage::new().components(self.generate_components(pages, index, roles));
+        let result = if self.style == MenuStyle::Reactions {
+            result.reactions(
+                pages[index]
+                    .iter()
+                    .filter_map(|role_id| self.get_emoji(*role_id).cloned()),
+            )
+        } else {
+            result
+        };
+        let result = match self.generate_embed(pages, index) {
+            Some(embed) => result.embed(embed).content(""),
+            None => result.content(NO_ROLES_CONTENT),
+        };
+
+        channel_id
+            .send_message(ctx, result)
+            .await
+            .map(|message| message.id)
+    }
+
+    async fn message_exists(&self, ctx: &Context, channel_id: ChannelId) -> bool {
+        match self.message_id {
+            Some(message_id) => ctx
+                .http
+                .get_message(channel_id, message_id)
+                .await
+                .ok()
+                .is_some(),
+            None => false,
+        }
+    }
+
+    /// Components cannot render role mentions, so they are labelled with the role's name instead
+    async fn fetch_roles(&self, ctx: &Context, guild_id: GuildId) -> HashMap<RoleId, Role> {
+        if self.style == MenuStyle::Reactions || self.bundles.is_empty() {
+            return HashMap::new();
+        }
+        guild_id.roles(ctx).await.unwrap_or_else(|e| {
+            error!("Could not fetch roles for guild {:?}: {:?}", guild_id, e);
+            HashMap::new()
+        })
+    }
+
+    /// Splits the menu's roles across as many messages as Discord's limits on reactions,
+    /// components and embed length require. A role only moves to another message when roles
+    /// before it are added, removed, described or reordered.
+    fn generate_pages(&self) -> Vec<Vec<u64>> {
+        let capacity = match self.style {
+            MenuStyle::Reactions => REACTIONS_PER_MESSAGE,
+            MenuStyle::Buttons => BUTTONS_PER_MESSAGE,
+            MenuStyle::Select => OPTIONS_PER_SELECT,
+        };
+        let length = |text: &Option<String>| text.as_ref().map_or(0, |text| text.chars().count());
+        let appearance = &self.appearance;
+        let window = self
+            .generate_window_field()
+            .map_or(0, |(name, value)| name.len() + value.chars().count());
+        let budget = EMBED_MAX_LENGTH
+            .saturating_sub(length(&appearance.footer) + ROLES_FIELD_NAME.chars().count() + window);
+        let first_budget =
+            budget.saturating_sub(length(&appearance.title) + length(&appearance.description));
+
+        let mut pages = vec![Vec::new()];
+        let mut used = 0;
+        for role_id in self.get_role_ids() {
+            // Each line may need a field of its own, whose name counts towards the embed's length
+            let cost = self
+                .generate_line(role_id)
+                .map_or(0, |line| line.chars().count())
+                + CONTINUED_FIELD_NAME.chars().count();
+            let page_budget = if pages.len() == 1 {
+                first_budget
+            } else {
+                budget
+            };
+            let page = pages.last().expect("At least one page");
+            if !page.is_empty() && (page.len() >= capacity || used + cost > page_budget) {
+                pages.push(Vec::new());
+                used = 0;
+            }
+            pages.last_mut().expect("At least one page").push(role_id);
+            used += cost;
+        }
+        pages
+    }
+
+    /// The first message carries the menu's title, description and thumbnail and the last its
+    /// image, while every message has the footer
+    fn generate_embed(&self, pages: &[Vec<u64>], index: usize) -> Option<CreateEmbed> {
+        let fields = self.generate_fields(&pages[index]);
+
+        if fields.is_empty() {
+            None
+        } else {
+            let appearance = &self.appearance;
+            let mut embed = CreateEmbed::new()
+                .color(appearance.color.map_or(Color::DARKER_GREY, Color::new))
+                .fields(fields.into_iter().enumerate().map(|(number, value)| {
+                    let name = if number == 0 {
+                        ROLES_FIELD_NAME
+                    } else {
+                        CONTINUED_FIELD_NAME
+                    };
+                    (name, value, true)
+                }));
+            if index == 0 {
+                if let Some(title) = &appearance.title {
+                    embed = embed.title(title);
+                }
+                if let Some(description) = &appearance.description {
+                    embed = embed.description(description);
+                }
+                if let Some(thumbnail) = &appearance.thumbnail {
+                    embed = embed.thumbnail(thumbnail);
+                }
+            }
+            if index + 1 == pages.len() {
+                if let Some(image) = &appearance.image {
+                    embed = embed.image(image);
+                }
+            }
+            if let Some((name, value)) = self.generate_window_field() {
+                embed = embed.field(name, value, false);
+            }
+            if let Some(footer) = &appearance.footer {
+                embed = embed.footer(CreateEmbedFooter::new(footer));
+            }
+            Some(embed)
+        }
+    }
+
+    /// Tells members when a menu with a window opens or closes, using timestamps Discord shows in
+    /// each member's own time zone
+    fn generate_window_field(&self) -> Option<(&'static str, String)> {
+        let timestamp = |time: Timestamp| {
+            let time = time.unix_timestamp();
+            format!("<t:{time}:F> (<t:{time}:R>)")
+        };
+        match self.window.get_state(Timestamp::now()) {
+            WindowState::NotYetOpen => self
+                .window
+                .opens_at
+                .map(|opens_at| ("Opens", timestamp(opens_at))),
+            WindowState::Open => self
+                .window
+                .closes_at
+                .map(|closes_at| ("Closes", timestamp(closes_at))),
+            WindowState::Closed => Some(("Closed", "This menu no longer takes picks".to_owned())),
+        }
+    }
+
+    fn generate_components(
+        &self,
+        pages: &[Vec<u64>],
+        index: usize,
+        roles: &HashMap<RoleId, Role>,
+    ) -> Vec<CreateActionRow> {
+        let page = &pages[index];
+        if self.style == MenuStyle::Reactions || page.is_empty() {
+            return Vec::new();
+        }
+
+        let role_name = |role_id: &u64| {
+            roles
+                .get(&RoleId::new(*role_id))
+                .map_or_else(|| role_id.to_string(), |role| role.name.clone())
+        };
+        let closed = self.window.get_state(Timestamp::now()) != WindowState::Open;
+        let emoji = |role_id: &u64| {
+            self.get_emoji(*role_id)
+                .expect("Every enabled role has an emoji")
+                .clone()
+        };
+
+        match self.style {
+            MenuStyle::Reactions => Vec::new(),
+            MenuStyle::Buttons => {
+                let buttons: Vec<CreateButton> = page
+                    .iter()
+                    .map(|role_id| {
+                        CreateButton::new(format!("{TOGGLE_ROLE_PREFIX}{role_id}"))
+                            .style(ButtonStyle::Secondary)
+                            .emoji(emoji(role_id))
+                            .label(truncate_label(role_name(role_id)))
+                            .disabled(closed)
+                    })
+                    .collect();
+
+                buttons
+                    .chunks(BUTTONS_PER_ROW)
+                    .map(|row| CreateActionRow::Buttons(row.to_vec()))
+                    .collect()
+            }
+            MenuStyle::Select => {
+                let options: Vec<CreateSelectMenuOption> = page
+                    .iter()
+                    .map(|role_id| {
+                        let option =
+                            CreateSelectMenuOption::new(role_name(role_id), role_id.to_string())
+                                .emoji(emoji(role_id));
+                        match self.descriptions.get(role_id) {
+                            Some(description) => option.description(description),
+                            None => option,
+                        }
+                    })
+                    .collect();
+
+                // Discord rejects limits outside of the number of options, so clamp them to fit.
+                // Members of an exclusive menu may pick at most one role. A minimum cannot be
+                // enforced across several dropdowns, so menus spread over several messages have
+                // none.
+                let role_count = u8::try_from(options.len()).unwrap_or(u8::MAX);
+                let limit = if self.exclusive { 1 } else { role_count };
+                let min = if pages.len() > 1 {
+                    0
+                } else {
+                    self.min_choices.min(limit)
+                };
+                let max = self.max_choices.unwrap_or(limit).clamp(min.max(1), limit);
+
+                vec![CreateActionRow::SelectMenu(
+                    CreateSelectMenu::new(
+                        SELECT_ROLES_ID,
+                        CreateSelectMenuKind::String { options },
+                    )
+                    .placeholder("Choose your roles")
+                    .min_values(min)
+                    .max_values(max)
+                    .disabled(closed),
+                )]
+            }
+        }
+    }
+
+    /// Lists the roles one per line, split across as many fields as their length requires
+    fn generate_fields(&self, page: &[u64]) -> Vec<String> {
+        let mut fields: Vec<String> = Vec::new();
+        for line in page
+            .iter()
+            .filter_map(|role_id| self.generate_line(*role_id))
+        {
+            match fields.last_mut() {
+                Some(field) if field.chars().count() + line.chars().count() <= FIELD_MAX_LENGTH => {
+                    field.push_str(&line);
+                }
+                _ => fields.push(line),
+            }
+        }
+        fields
+    }
+
+    fn generate_line(&self, role_id: u64) -> Option<String> {
+        let mut result = String::new();
+        let roles = self
+            .get_bundle(role_id)
+            .iter()
+            .map(|role| format!("<@&{role}>"))
+            .collect::<Vec<_>>()
+            .join(" + ");
+
+        match self.get_emoji(role_id)? {
+            ReactionType::Custom { animated, id, name } => {
+                if *animated {
+                    write!(
+                        result,
+                        "{}: <a:{}:{}>",
+                        roles,
+                        name.as_ref().expect("A named emoji"),
+                        id
+                    )
+                    .expect("String concatenation success");
+                } else {
+                    write!(
+                        result,
+                        "{}: <:{}:{}>",
+                        roles,
+                        name.as_ref().expect("A named emoji"),
+                        id
+                    )
+                    .expect("String concatenation success");
+                }
+            }
+            ReactionType::Unicode(char) => {
+                write!(result, "{}: {}", roles, char).expect("String concatenation success");
+            }
+            kind => {
+                error!("Unknown reaction {kind}, Discord may have made API changes");
+                return None;
+            }
+        }
+        match self.descriptions.get(&role_id) {
+            Some(description) => writeln!(result, " — {description}"),
+            None => writeln!(result),
+        }
+        .expect("String concatenation success");
+
+        Some(result)
+    }
+}
+
+/// Shortens a button label that Discord would reject, marking that it was cut off
+fn truncate_label(label: String) -> String {
+    if label.chars().count() <= BUTTON_LABEL_MAX_LENGTH {
+        return label;
+    }
+    let mut truncated: String = label.chars().take(BUTTON_LABEL_MAX_LENGTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,39 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::{model::id::GuildId, prelude::Context};
+
+use crate::database::Database;
+
+/// How often the bot looks for menus whose window has opened or closed
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Redraws menus as their windows open and close, for as long as the bot runs, so their messages
+/// say whether members can pick roles
+pub async fn update_windows_periodically(ctx: Context, db: Arc<Database>) {
+    let mut interval = tokio::time::interval(WINDOW_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for guild_id in ctx.cache.guilds() {
+            update_windows(&ctx, &db, guild_id).await;
+        }
+    }
+}
+
+async fn update_windows(ctx: &Context, db: &Database, guild_id: GuildId) {
+    let outdated = db
+        .get_guild_data(guild_id)
+        .is_some_and(|data| data.get_menus().any(|(_, menu)| menu.is_window_outdated()));
+    if !outdated {
+        return;
+    }
+
+    let Ok(mut data) = db.transaction(guild_id).await else {
+        return;
+    };
+    for (_, menu) in data.get_menus_mut() {
+        if menu.is_window_outdated() {
+            menu.refresh_message(ctx, guild_id).await;
+        }
+    }
+    data.commit();
+}
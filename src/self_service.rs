@@ -0,0 +1,596 @@
+use std::collections::BTreeSet;
+
+use log::{error, warn};
+use serenity::{
+    builder::{
+        CreateAllowedMentions, CreateInteractionResponse, CreateInteractionResponseMessage,
+        CreateMessage,
+    },
+    futures::TryFutureExt,
+    model::{
+        application::{ComponentInteraction, ComponentInteractionDataKind},
+        guild::Member,
+        id::{GuildId, RoleId, UserId},
+    },
+    prelude::Context,
+};
+
+use crate::{
+    audit_log::AuditLog,
+    database::Database,
+    expiry::{cancel_expiry, schedule_expiry},
+    guild_data::GuildData,
+    role_menu::{MenuStyle, RoleMenu, TOGGLE_ROLE_PREFIX},
+};
+
+pub async fn respond_to_component<S>(ctx: &Context, component: &ComponentInteraction, content: S)
+where
+    S: Into<String>,
+{
+    if let Err(e) = component
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .allowed_mentions(CreateAllowedMentions::new())
+                    .content(content),
+            ),
+        )
+        .await
+    {
+        error!("Could not respond to component interaction: {:?}", e);
+    }
+}
+
+/// What became of a member's reaction for one of a menu's roles
+pub enum ReactionOutcome {
+    Granted,
+    /// The menu did not allow the pick, so the reaction was removed
+    Rejected,
+    Failed,
+}
+
+/// Gives the member the role they reacted with, along with giving up any roles it excludes
+pub async fn add_reaction_role(
+    ctx: &Context,
+    db: &Database,
+    data: &GuildData,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    user_id: UserId,
+    role_id: u64,
+) -> ReactionOutcome {
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => {
+            // Members who already hold the role are never rejected, as removing their reaction
+            // would take the role away from them
+            if !member.roles.contains(&RoleId::new(role_id)) {
+                if let Some(reason) = reject_reaction(ctx, menu, guild_id, &member, role_id).await {
+                    remove_member_reaction(ctx, menu, user_id, role_id).await;
+                    notify_reaction_removed(ctx, menu, guild_id, user_id, reason).await;
+                    return ReactionOutcome::Rejected;
+                }
+            }
+            if let Err(e) =
+                grant_bundle(ctx, menu, guild_id, user_id, &member.roles, role_id, None).await
+            {
+                error!("Could not add role to user {:?}: {:?}", user_id, e);
+                return ReactionOutcome::Failed;
+            }
+            data.get_audit_log()
+                .role_granted(ctx, menu, guild_id, user_id, role_id)
+                .await;
+            schedule_expiry(db, guild_id, menu, user_id, role_id).await;
+            remove_exclusive_peers(ctx, db, data, menu, guild_id, &member, role_id).await;
+            ReactionOutcome::Granted
+        }
+        Err(e) => {
+            error!("Could not add role to user {:?}: {:?}", user_id, e);
+            ReactionOutcome::Failed
+        }
+    }
+}
+
+/// Explains why the member may not pick the role by reacting, if they may not
+async fn reject_reaction(
+    ctx: &Context,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    member: &Member,
+    role_id: u64,
+) -> Option<String> {
+    if let Some(reason) = menu.describe_closed() {
+        return Some(reason);
+    }
+    if let Some(unmet) = menu.check_requirements(&member.roles, role_id) {
+        let role = get_role_name(ctx, guild_id, unmet.get_role_id()).await;
+        return Some(unmet.describe(&role));
+    }
+    if menu.would_exceed_limit(&member.roles, role_id) {
+        return Some(format!(
+            "you can hold at most {} roles from it. \
+             Remove one of your other reactions first to pick a different role",
+            menu.get_max_choices().unwrap_or_default()
+        ));
+    }
+    None
+}
+
+/// Takes away the role the member un-reacted, if they still hold it
+pub async fn remove_reaction_role(
+    ctx: &Context,
+    db: &Database,
+    data: &GuildData,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    user_id: UserId,
+    role_id: u64,
+) {
+    match guild_id.member(ctx, user_id).await {
+        // The bot only rejects reactions from members without the role, so removing those leaves
+        // nothing to undo
+        Ok(member) if !menu.holds_bundle(&member.roles, role_id) => {}
+        Ok(member) => {
+            let roles = data.get_roles_to_revoke(menu, role_id, &member.roles);
+            if let Err(e) = revoke_roles(ctx, guild_id, user_id, &roles, None).await {
+                error!("Could not remove role from user {:?}: {:?}", user_id, e);
+                return;
+            }
+            data.get_audit_log()
+                .role_removed(ctx, menu, guild_id, user_id, role_id)
+                .await;
+            cancel_expiry(db, guild_id, user_id, role_id).await;
+        }
+        Err(e) => error!("Could not remove role from user {:?}: {:?}", user_id, e),
+    }
+}
+
+/// Takes away the member's roles that are exclusive with a role they just picked, along with
+/// their reactions for those roles
+async fn remove_exclusive_peers(
+    ctx: &Context,
+    db: &Database,
+    data: &GuildData,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    member: &Member,
+    role_id: u64,
+) {
+    let user_id = member.user.id;
+    let peers = menu.get_exclusive_peers(role_id);
+    // Once the peers are gone, the member's picks are their other roles and the one just taken
+    let picked: Vec<RoleId> = member
+        .roles
+        .iter()
+        .copied()
+        .filter(|role| !peers.contains(&role.get()))
+        .chain([RoleId::new(role_id)])
+        .collect();
+    for peer in peers {
+        if menu.holds_bundle(&member.roles, peer) {
+            let roles = data.get_roles_to_revoke(menu, peer, &picked);
+            match revoke_roles(ctx, guild_id, user_id, &roles, None).await {
+                Ok(()) => {
+                    data.get_audit_log()
+                        .role_removed(ctx, menu, guild_id, user_id, peer)
+                        .await;
+                    cancel_expiry(db, guild_id, user_id, peer).await;
+                }
+                Err(e) => error!("Could not remove role from user {:?}: {:?}", user_id, e),
+            }
+            // Members react for the roles they hold, so the other peers' reactions are left
+            // alone rather than spending a request on each role of a wholly exclusive menu
+            remove_member_reaction(ctx, menu, user_id, peer).await;
+        }
+    }
+}
+
+/// Gives the member every role in the bundle picked with the role. If one cannot be given, the
+/// roles given so far that the member did not already hold are taken back, so the member is never
+/// left with part of the bundle.
+pub async fn grant_bundle(
+    ctx: &Context,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    user_id: UserId,
+    member_roles: &[RoleId],
+    role_id: u64,
+    reason: Option<&str>,
+) -> serenity::Result<()> {
+    let mut granted = Vec::new();
+    for role in menu.get_bundle(role_id).into_iter().map(RoleId::new) {
+        if let Err(e) = ctx
+            .http
+            .add_member_role(guild_id, user_id, role, reason)
+            .await
+        {
+            for role in granted {
+                if let Err(e) = ctx
+                    .http
+                    .remove_member_role(guild_id, user_id, role, reason)
+                    .await
+                {
+                    error!(
+                        "Could not take back role {:?} from user {:?}: {:?}",
+                        role, user_id, e
+                    );
+                }
+            }
+            return Err(e);
+        }
+        if !member_roles.contains(&role) {
+            granted.push(role);
+        }
+    }
+    Ok(())
+}
+
+/// Takes away the roles of a bundle the member gave up, as found by
+/// [`GuildData::get_roles_to_revoke`]
+pub async fn revoke_roles(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    roles: &[u64],
+    reason: Option<&str>,
+) -> serenity::Result<()> {
+    for role in roles {
+        ctx.http
+            .remove_member_role(guild_id, user_id, RoleId::new(*role), reason)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Takes back the member's reaction for a role on a reaction-style menu
+pub async fn remove_member_reaction(ctx: &Context, menu: &RoleMenu, user_id: UserId, role_id: u64) {
+    if let (MenuStyle::Reactions, Some(channel_id), Some(message_id), Some(emoji)) = (
+        menu.get_style(),
+        menu.get_channel_id(),
+        menu.get_role_message_id(role_id),
+        menu.get_emoji(role_id),
+    ) {
+        if let Err(e) = ctx
+            .http
+            .delete_reaction(channel_id, message_id, user_id, emoji)
+            .await
+        {
+            error!("Could not remove reaction from user {:?}: {:?}", user_id, e);
+        }
+    }
+}
+
+/// Reactions cannot be answered ephemerally, so members whose reaction was rejected are told why
+/// by DM
+async fn notify_reaction_removed(
+    ctx: &Context,
+    menu: &RoleMenu,
+    guild_id: GuildId,
+    user_id: UserId,
+    reason: String,
+) {
+    if let (Some(channel_id), Some(message_id)) = (menu.get_channel_id(), menu.get_message_id()) {
+        let content = format!(
+            "Your reaction to {} was removed because {reason}.",
+            message_id.link(channel_id, Some(guild_id))
+        );
+        if let Err(e) = user_id
+            .create_dm_channel(ctx)
+            .and_then(|channel| async move {
+                channel
+                    .send_message(ctx, CreateMessage::new().content(content))
+                    .await
+            })
+            .await
+        {
+            error!("Could not message user {:?}: {:?}", user_id, e);
+        }
+    }
+}
+
+/// Role mentions do not render in DMs, so messages sent there name roles instead
+async fn get_role_name(ctx: &Context, guild_id: GuildId, role_id: u64) -> String {
+    guild_id
+        .roles(ctx)
+        .await
+        .ok()
+        .and_then(|roles| {
+            roles
+                .get(&RoleId::new(role_id))
+                .map(|role| role.name.clone())
+        })
+        .unwrap_or_else(|| role_id.to_string())
+}
+
+/// Gives or takes away the role behind a menu button from the member who clicked it
+pub async fn toggle_role(ctx: &Context, db: &Database, component: &ComponentInteraction) {
+    let role_id = component
+        .data
+        .custom_id
+        .strip_prefix(TOGGLE_ROLE_PREFIX)
+        .and_then(|id| id.parse::<u64>().ok());
+
+    match (component.guild_id, component.member.as_ref(), role_id) {
+        (Some(guild_id), Some(member), Some(role_id)) => {
+            let guild_data = db.get_guild_data(guild_id);
+            let found = guild_data.as_ref().and_then(|data| {
+                data.find_menu_by_message(component.message.id)
+                    .filter(|menu| menu.has_role(role_id))
+                    .map(|menu| (data, menu))
+            });
+
+            let Some((data, menu)) = found else {
+                respond_to_component(
+                    ctx,
+                    component,
+                    "That role is no longer available for self-service",
+                )
+                .await;
+                return;
+            };
+            let audit_log = data.get_audit_log();
+
+            let role = RoleId::new(role_id);
+            let user_id = member.user.id;
+            if member.roles.contains(&role) {
+                let roles = data.get_roles_to_revoke(menu, role_id, &member.roles);
+                match revoke_roles(ctx, guild_id, user_id, &roles, None).await {
+                    Ok(()) => {
+                        audit_log
+                            .role_removed(ctx, menu, guild_id, user_id, role_id)
+                            .await;
+                        cancel_expiry(db, guild_id, user_id, role_id).await;
+                        respond_to_component(ctx, component, format!("Removed <@&{role_id}>"))
+                            .await;
+                    }
+                    Err(e) => {
+                        error!("Could not remove role from user {:?}: {:?}", user_id, e);
+                        respond_to_component(ctx, component, "Could not remove that role").await;
+                    }
+                }
+            } else if let Some(reason) = menu.describe_closed() {
+                respond_to_component(
+                    ctx,
+                    component,
+                    format!("You cannot pick <@&{role_id}>, {reason}"),
+                )
+                .await;
+            } else if let Some(unmet) = menu.check_requirements(&member.roles, role_id) {
+                let reason = unmet.describe(&format!("<@&{}>", unmet.get_role_id()));
+                respond_to_component(
+                    ctx,
+                    component,
+                    format!("You cannot pick <@&{role_id}>, {reason}"),
+                )
+                .await;
+            } else if menu.would_exceed_limit(&member.roles, role_id) {
+                respond_to_component(
+                    ctx,
+                    component,
+                    format!(
+                        "You can hold at most {} roles from this menu, remove one first",
+                        menu.get_max_choices().unwrap_or_default()
+                    ),
+                )
+                .await;
+            } else {
+                match grant_bundle(ctx, menu, guild_id, user_id, &member.roles, role_id, None).await
+                {
+                    Ok(()) => {
+                        audit_log
+                            .role_granted(ctx, menu, guild_id, user_id, role_id)
+                            .await;
+                        schedule_expiry(db, guild_id, menu, user_id, role_id).await;
+                        remove_exclusive_peers(ctx, db, data, menu, guild_id, member, role_id)
+                            .await;
+                        respond_to_component(ctx, component, format!("Added <@&{role_id}>")).await;
+                    }
+                    Err(e) => {
+                        error!("Could not add role to user {:?}: {:?}", user_id, e);
+                        respond_to_component(ctx, component, "Could not add that role").await;
+                    }
+                }
+            }
+        }
+        _ => warn!("A role button was clicked with an unexpected ID or outside of a guild"),
+    }
+}
+
+/// Reconciles the member's roles from a select-style menu to exactly the ones they picked
+pub async fn select_roles(ctx: &Context, db: &Database, component: &ComponentInteraction) {
+    match (
+        component.guild_id,
+        component.member.as_ref(),
+        &component.data.kind,
+    ) {
+        (Some(guild_id), Some(member), ComponentInteractionDataKind::StringSelect { values }) => {
+            let guild_data = db.get_guild_data(guild_id);
+            let menu = guild_data
+                .as_ref()
+                .and_then(|data| data.find_menu_by_message(component.message.id));
+            // Menus spread over several messages have a dropdown on each, which only covers the
+            // roles shown alongside it
+            let page_roles: Vec<RoleId> = menu
+                .map(|menu| {
+                    menu.get_page_roles(component.message.id)
+                        .into_iter()
+                        .map(RoleId::new)
+                        .collect()
+                })
+                .unwrap_or_default();
+            let chosen: Vec<RoleId> = values
+                .iter()
+                .filter_map(|value| value.parse::<u64>().ok().map(RoleId::new))
+                .filter(|role| page_roles.contains(role))
+                .collect();
+            // Roles picked from the menu's other messages give way to exclusive roles chosen here
+            let displaced: BTreeSet<RoleId> = menu
+                .map(|menu| {
+                    chosen
+                        .iter()
+                        .flat_map(|role| menu.get_exclusive_peers(role.get()))
+                        .map(RoleId::new)
+                        .filter(|peer| {
+                            !page_roles.contains(peer)
+                                && menu.holds_bundle(&member.roles, peer.get())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if let Some(menu) = menu {
+                // Members may still give up roles once the menu has closed, but not pick new ones
+                if let Some(reason) = menu
+                    .describe_closed()
+                    .filter(|_| chosen.iter().any(|role| !member.roles.contains(role)))
+                {
+                    respond_to_component(
+                        ctx,
+                        component,
+                        format!("You cannot pick new roles, {reason}"),
+                    )
+                    .await;
+                    return;
+                }
+
+                let conflict = chosen.iter().find_map(|role| {
+                    let peers = menu.get_exclusive_peers(role.get());
+                    chosen
+                        .iter()
+                        .find(|other| peers.contains(&other.get()))
+                        .map(|other| (role, other))
+                });
+                if let Some((role, other)) = conflict {
+                    respond_to_component(
+                        ctx,
+                        component,
+                        format!("You can only pick one of <@&{role}> and <@&{other}>"),
+                    )
+                    .await;
+                    return;
+                }
+
+                // Requirements only gate taking new roles, so members keep roles they already hold
+                let unmet = chosen
+                    .iter()
+                    .filter(|role| !member.roles.contains(role))
+                    .find_map(|role| {
+                        menu.check_requirements(&member.roles, role.get())
+                            .map(|unmet| (role, unmet))
+                    });
+                if let Some((role, unmet)) = unmet {
+                    let reason = unmet.describe(&format!("<@&{}>", unmet.get_role_id()));
+                    respond_to_component(
+                        ctx,
+                        component,
+                        format!("You cannot pick <@&{role}>, {reason}"),
+                    )
+                    .await;
+                    return;
+                }
+
+                let held_elsewhere = menu
+                    .get_picked_roles(&member.roles)
+                    .into_iter()
+                    .map(RoleId::new)
+                    .filter(|role| !page_roles.contains(role) && !displaced.contains(role))
+                    .count();
+                if let Some(max) = menu
+                    .get_max_choices()
+                    .filter(|max| held_elsewhere + chosen.len() > usize::from(*max))
+                {
+                    respond_to_component(
+                        ctx,
+                        component,
+                        format!(
+                            "You can hold at most {max} roles from this menu, including those \
+                             picked from its other messages"
+                        ),
+                    )
+                    .await;
+                    return;
+                }
+            }
+
+            let audit_log = guild_data
+                .as_ref()
+                .map(GuildData::get_audit_log)
+                .unwrap_or(AuditLog::new(None));
+            let user_id = member.user.id;
+            // The roles the member picked once the change goes through, whose bundles are kept
+            let picked: Vec<RoleId> = member
+                .roles
+                .iter()
+                .copied()
+                .filter(|role| !page_roles.contains(role) && !displaced.contains(role))
+                .chain(chosen.iter().copied())
+                .collect();
+            let mut failed = false;
+            for role in page_roles.iter().chain(&displaced) {
+                // Page roles only exist for menus, so there is always one here
+                let (Some(data), Some(menu)) = (guild_data.as_ref(), menu) else {
+                    break;
+                };
+                let held = menu.holds_bundle(&member.roles, role.get());
+                let result = match (member.roles.contains(role), held, chosen.contains(role)) {
+                    (false, _, true) => {
+                        let result = grant_bundle(
+                            ctx,
+                            menu,
+                            guild_id,
+                            user_id,
+                            &member.roles,
+                            role.get(),
+                            None,
+                        )
+                        .await;
+                        if result.is_ok() {
+                            audit_log
+                                .role_granted(ctx, menu, guild_id, user_id, role.get())
+                                .await;
+                            schedule_expiry(db, guild_id, menu, user_id, role.get()).await;
+                        }
+                        result
+                    }
+                    (_, true, false) => {
+                        let roles = data.get_roles_to_revoke(menu, role.get(), &picked);
+                        let result = revoke_roles(ctx, guild_id, user_id, &roles, None).await;
+                        if result.is_ok() {
+                            audit_log
+                                .role_removed(ctx, menu, guild_id, user_id, role.get())
+                                .await;
+                            cancel_expiry(db, guild_id, user_id, role.get()).await;
+                        }
+                        result
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    error!(
+                        "Could not update role {:?} for user {:?}: {:?}",
+                        role, user_id, e
+                    );
+                    failed = true;
+                }
+            }
+
+            let content = if failed {
+                "Some of your roles could not be updated".to_owned()
+            } else if chosen.is_empty() {
+                "You no longer have any roles from this menu".to_owned()
+            } else {
+                format!(
+                    "Your roles from this menu are now {}",
+                    chosen
+                        .iter()
+                        .map(|role| format!("<@&{role}>"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            };
+            respond_to_component(ctx, component, content).await;
+        }
+        _ => warn!("A role menu selection was made with unexpected data or outside of a guild"),
+    }
+}
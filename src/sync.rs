@@ -0,0 +1,275 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    ops::AddAssign,
+};
+
+use log::{error, info};
+use serenity::{
+    model::{
+        guild::Member,
+        id::{GuildId, MessageId, RoleId, UserId},
+    },
+    prelude::Context,
+};
+
+use crate::{
+    database::Database,
+    expiry::cancel_expiry,
+    guild_data::GuildData,
+    role_menu::{MenuStyle, RoleMenu},
+    self_service::{add_reaction_role, revoke_roles, ReactionOutcome},
+    util::is_unknown_member,
+};
+
+/// Discord returns at most 100 users per page of a reaction's users
+const REACTION_USERS_PAGE_SIZE: u8 = 100;
+
+/// Discord returns at most 1000 members per page of a guild's members
+const MEMBERS_PAGE_SIZE: u64 = 1000;
+
+/// Counts of the changes made while bringing members' roles in line with their reactions
+#[derive(Default)]
+pub struct SyncSummary {
+    granted: usize,
+    revoked: usize,
+    rejected: usize,
+}
+
+impl SyncSummary {
+    pub fn has_changes(&self) -> bool {
+        self.granted + self.revoked + self.rejected > 0
+    }
+}
+
+impl AddAssign for SyncSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.granted += other.granted;
+        self.revoked += other.revoked;
+        self.rejected += other.rejected;
+    }
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "granted {} roles, revoked {} roles and removed {} reactions the menu does not allow",
+            self.granted, self.revoked, self.rejected
+        )
+    }
+}
+
+/// Brings members' roles from a reaction-style menu in line with their reactions, catching up on
+/// reactions added or removed while the bot was offline.
+///
+/// Reactions are handled as if they had just been added, so the menu's window, requirements,
+/// limit and exclusive roles all apply. Only when `revoke` is set do members who hold one of the
+/// menu's roles without reacting for it lose the role, unless they kept it when the menu was moved
+/// or another of the guild's menus also hands it out. Finding those members means listing every
+/// member, which requires the Server Members intent; otherwise only the members who reacted are
+/// looked up.
+pub async fn sync_menu(
+    ctx: &Context,
+    db: &Database,
+    guild_id: GuildId,
+    data: &GuildData,
+    menu_name: &str,
+    revoke: bool,
+) -> serenity::Result<SyncSummary> {
+    let mut summary = SyncSummary::default();
+    let Some(menu) = data
+        .get_menu(menu_name)
+        .filter(|menu| menu.get_style() == MenuStyle::Reactions && menu.get_message_id().is_some())
+    else {
+        return Ok(summary);
+    };
+
+    let reactors = get_reactors(ctx, menu).await?;
+    if !revoke && reactors.values().all(HashMap::is_empty) {
+        return Ok(summary);
+    }
+    let shared = data.get_roles_from_other_menus(menu_name);
+
+    let members = match revoke {
+        true => get_members(ctx, guild_id).await?,
+        false => get_reacting_members(ctx, guild_id, &reactors).await?,
+    };
+    for member in members {
+        if member.user.bot {
+            continue;
+        }
+        let user_id = member.user.id;
+        let reacted = |role_id: u64| {
+            reactors
+                .get(&role_id)
+                .is_some_and(|users| users.contains_key(&user_id))
+        };
+
+        let mut granting = false;
+        for role_id in menu.get_role_ids() {
+            if !reacted(role_id) || member.roles.contains(&RoleId::new(role_id)) {
+                continue;
+            }
+            granting = true;
+            match add_reaction_role(ctx, db, data, menu, guild_id, user_id, role_id).await {
+                ReactionOutcome::Granted => summary.granted += 1,
+                ReactionOutcome::Rejected => summary.rejected += 1,
+                ReactionOutcome::Failed => {}
+            }
+        }
+        if !revoke {
+            continue;
+        }
+
+        // Picking an exclusive role takes away its peers, so the member's roles may have changed
+        let member = match granting {
+            true => guild_id.member(ctx, user_id).await?,
+            false => member,
+        };
+        // Members who picked the role on a menu's previous message cannot have reacted yet
+        let is_picked = |role_id: u64| reacted(role_id) || menu.is_carried_over(role_id, user_id);
+        // Roles held from the guild's other menus count as picked, so their bundles are kept
+        let picked: Vec<RoleId> = member
+            .roles
+            .iter()
+            .copied()
+            .filter(|role| !menu.has_role(role.get()) || is_picked(role.get()))
+            .collect();
+        for role_id in menu.get_role_ids() {
+            if !menu.holds_bundle(&member.roles, role_id)
+                || is_picked(role_id)
+                || shared.contains(&role_id)
+            {
+                continue;
+            }
+            let reason = Some("Reaction sync");
+            let roles = data.get_roles_to_revoke(menu, role_id, &picked);
+            revoke_roles(ctx, guild_id, user_id, &roles, reason).await?;
+            cancel_expiry(db, guild_id, user_id, role_id).await;
+            summary.revoked += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Pages through the members who reacted for each of a reaction-style menu's roles, on any of
+/// the menu's messages, noting which message each member reacted on
+pub async fn get_reactors(
+    ctx: &Context,
+    menu: &RoleMenu,
+) -> serenity::Result<HashMap<u64, HashMap<UserId, MessageId>>> {
+    let mut reactors: HashMap<u64, HashMap<UserId, MessageId>> = menu
+        .get_role_ids()
+        .into_iter()
+        .map(|role_id| (role_id, HashMap::new()))
+        .collect();
+    let Some(channel_id) = menu.get_channel_id() else {
+        return Ok(reactors);
+    };
+
+    let message_ids: Vec<MessageId> = menu.get_message_ids().collect();
+    for message_id in message_ids {
+        // Only the emoji someone has reacted with are worth paging through
+        let message = channel_id.message(ctx, message_id).await?;
+        for reaction in message.reactions {
+            let Some(role_id) = menu.get_role(&reaction.reaction_type).copied() else {
+                continue;
+            };
+            let users = reactors.entry(role_id).or_default();
+            let mut after = None;
+            loop {
+                let page = ctx
+                    .http
+                    .get_reaction_users(
+                        channel_id,
+                        message_id,
+                        &reaction.reaction_type,
+                        REACTION_USERS_PAGE_SIZE,
+                        after,
+                    )
+                    .await?;
+                after = page.last().map(|user| user.id.get());
+                let done = page.len() < usize::from(REACTION_USERS_PAGE_SIZE);
+                users.extend(
+                    page.into_iter()
+                        .filter(|user| !user.bot)
+                        .map(|user| (user.id, message_id)),
+                );
+                if done {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(reactors)
+}
+
+/// Grants the roles members reacted for on every menu in the guild while the bot was offline,
+/// logging what changed. Roles are only revoked by an admin's explicit sync, as members may hold
+/// them for reasons the bot does not know about.
+pub async fn sync_guild(ctx: &Context, db: &Database, guild_id: GuildId) {
+    if let Some(data) = db.get_guild_data(guild_id) {
+        let audit_log = data.get_audit_log();
+        for (name, _) in data.get_menus() {
+            match sync_menu(ctx, db, guild_id, &data, name, false).await {
+                Ok(summary) => {
+                    info!(
+                        "Synced the {} menu for guild {:?}: {}",
+                        name, guild_id, summary
+                    );
+                    // Most startups find nothing to catch up on, which is not worth a log entry
+                    if summary.has_changes() {
+                        audit_log.menu_synced(ctx, name, &summary).await;
+                    }
+                }
+                Err(e) => error!(
+                    "Could not sync the {} menu for guild {:?}: {:?}",
+                    name, guild_id, e
+                ),
+            }
+        }
+    }
+}
+
+/// Looks up the members who reacted on a menu one at a time, which unlike listing every member
+/// needs no privileged intent
+async fn get_reacting_members(
+    ctx: &Context,
+    guild_id: GuildId,
+    reactors: &HashMap<u64, HashMap<UserId, MessageId>>,
+) -> serenity::Result<Vec<Member>> {
+    let user_ids: BTreeSet<UserId> = reactors
+        .values()
+        .flat_map(|users| users.keys().copied())
+        .collect();
+    let mut members = Vec::new();
+    for user_id in user_ids {
+        match guild_id.member(ctx, user_id).await {
+            Ok(member) => members.push(member),
+            // Reactions stay behind when members leave the guild
+            Err(e) if is_unknown_member(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(members)
+}
+
+/// Pages through every member of the guild, which requires the Server Members intent
+pub async fn get_members(ctx: &Context, guild_id: GuildId) -> serenity::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut after = None;
+    loop {
+        let page = ctx
+            .http
+            .get_guild_members(guild_id, Some(MEMBERS_PAGE_SIZE), after)
+            .await?;
+        after = page.last().map(|member| member.user.id.get());
+        let done = page.len() < MEMBERS_PAGE_SIZE as usize;
+        members.extend(page);
+        if done {
+            return Ok(members);
+        }
+    }
+}
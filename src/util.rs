@@ -1,7 +1,96 @@
-use serenity::model::{application::CommandInteraction, id::GuildId};
+use serenity::{
+    http::HttpError,
+    model::{
+        application::{CommandDataOption, CommandDataOptionValue, CommandInteraction},
+        id::{ChannelId, GuildId, RoleId},
+    },
+};
+
+/// Discord's error code for a member who is not in the guild
+const UNKNOWN_MEMBER_CODE: isize = 10007;
 
 pub fn get_guild_id(command: &CommandInteraction) -> GuildId {
     command
         .guild_id
         .expect("Command is not allowed for use in DMs")
 }
+
+/// Finds the `/role self-service` subcommand that was invoked
+pub fn get_subcommand(command: &CommandInteraction) -> Option<&CommandDataOption> {
+    command
+        .data
+        .options
+        .first()
+        .and_then(|opt| match &opt.value {
+            CommandDataOptionValue::SubCommandGroup(group)
+                if command.data.name == "role" && opt.name == "self-service" =>
+            {
+                group.first()
+            }
+            _ => None,
+        })
+}
+
+/// Finds an optional integer argument, which Discord may send in any position
+pub fn get_integer_option(options: &[CommandDataOption], name: &str) -> Option<i64> {
+    options
+        .iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match opt.value {
+            CommandDataOptionValue::Integer(value) => Some(value),
+            _ => None,
+        })
+}
+
+/// Finds an optional string argument, which Discord may send in any position
+pub fn get_string_option<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match &opt.value {
+            CommandDataOptionValue::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+/// Finds an optional channel argument, which Discord may send in any position
+pub fn get_channel_option(options: &[CommandDataOption], name: &str) -> Option<ChannelId> {
+    options
+        .iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match opt.value {
+            CommandDataOptionValue::Channel(value) => Some(value),
+            _ => None,
+        })
+}
+
+/// Finds an optional boolean argument, which Discord may send in any position
+pub fn get_boolean_option(options: &[CommandDataOption], name: &str) -> Option<bool> {
+    options
+        .iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match opt.value {
+            CommandDataOptionValue::Boolean(value) => Some(value),
+            _ => None,
+        })
+}
+
+/// Finds an optional role argument, which Discord may send in any position
+pub fn get_role_option(options: &[CommandDataOption], name: &str) -> Option<RoleId> {
+    options
+        .iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match opt.value {
+            CommandDataOptionValue::Role(value) => Some(value),
+            _ => None,
+        })
+}
+
+/// Whether a request failed because the member is no longer in the guild
+pub fn is_unknown_member(error: &serenity::Error) -> bool {
+    matches!(
+        error,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response))
+            if response.error.code == UNKNOWN_MEMBER_CODE
+    )
+}